@@ -112,6 +112,10 @@ impl PreservedRegisters {
     pub fn rbp(&self) -> VirtAddr {
         VirtAddr::new({ self.rbp } as u64)
     }
+    /// 原始的rbp，被中断的代码可能把rbp用作通用寄存器，其中的值不一定是Canonical地址
+    pub fn raw_rbp(&self) -> u64 {
+        ({ self.rbp }) as u64
+    }
     pub fn rbx(&self) -> VirtAddr {
         VirtAddr::new({ self.rbx } as u64)
     }
@@ -166,6 +170,10 @@ impl IretRegisters {
     pub fn rip(&self) -> VirtAddr {
         VirtAddr::new({ self.rip } as u64)
     }
+    /// 原始的rip，在跳转到非Canonical地址引发的异常中不一定是Canonical地址
+    pub fn raw_rip(&self) -> u64 {
+        ({ self.rip }) as u64
+    }
     pub fn cs(&self) -> VirtAddr {
         VirtAddr::new({ self.cs } as u64)
     }
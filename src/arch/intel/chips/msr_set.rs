@@ -71,6 +71,10 @@ impl Efer {
     pub fn read(&self) -> EferFlags {
         EferFlags::from_bits_truncate(self.read_raw())
     }
+    /// 是否开启了不可执行页面保护，没有开启时页表项的第63位是保留位，不能设置`NO_EXECUTE`
+    pub fn is_no_execute_enabled(&self) -> bool {
+        self.read().contains(EferFlags::NO_EXECUTE_ENABLE)
+    }
 }
 
 /// FS.Base Model Specific Register.
@@ -11,4 +11,18 @@ pub unsafe fn flush_all() {
     use crate::arch::intel::chips::control::CR3;
     let (frame, flags) = CR3::read();
     CR3::write(frame, flags)
-}
\ No newline at end of file
+}
+/// 刷新包括全局页面在内的整个TLB
+/// 重新加载CR3不会刷新全局页面，因此在CR4启用了全局页面时先清除再恢复CR4.PGE
+#[inline]
+pub unsafe fn flush_all_global() {
+    use crate::arch::intel::chips::control::CR4;
+    use crate::arch::intel::chips::flags::CR4Flags;
+    let flags = CR4::flags();
+    if flags.contains(CR4Flags::PAGE_GLOBAL) {
+        CR4::write(flags - CR4Flags::PAGE_GLOBAL);
+        CR4::write(flags);
+    } else {
+        flush_all()
+    }
+}
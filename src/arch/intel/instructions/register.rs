@@ -89,6 +89,20 @@ pub fn read_rip() -> u64 {
     rip
 }
 
+/// 获取当前的RBP寄存器的值，只有在保留帧指针（`-C force-frame-pointers=yes`）时才指向当前栈帧
+#[allow(unused_assignments)]
+#[inline(always)]
+pub fn read_rbp() -> u64 {
+    let mut rbp: u64 = 0;
+    unsafe {
+        llvm_asm!(
+            "mov %rbp, $0"
+            : "=r"(rbp) ::: "volatile"
+        );
+    }
+    rbp
+}
+
 /// 从msr寄存器中读取64位数据
 #[allow(unused_assignments)]
 #[inline]
@@ -26,4 +26,16 @@ pub fn write_flags(flags: RFlags) {
     let reserved = old_value & !(RFlags::all().bits());
     let new_value = reserved | flags.bits();
     write(new_value);
-}
\ No newline at end of file
+}
+
+/// 设置RFLAGS.AC标志位，在启用SMAP时允许内核访问用户页面
+#[inline]
+pub unsafe fn stac() {
+    llvm_asm!("stac" ::: "memory" : "volatile");
+}
+
+/// 清除RFLAGS.AC标志位，恢复SMAP对用户页面的保护
+#[inline]
+pub unsafe fn clac() {
+    llvm_asm!("clac" ::: "memory" : "volatile");
+}
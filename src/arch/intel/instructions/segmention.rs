@@ -96,3 +96,26 @@ pub fn cs<S: Selector>() -> S {
     }
     S::from(segment)
 }
+
+/// 通过调用门远调用，目标代码需要使用`lretq`返回
+/// 64位调用门忽略远指针中的偏移量，目标地址由调用门描述符决定，
+/// 发生特权级转换时处理器从TSS中加载新的栈，并在新栈中保存原来的SS、RSP、CS和RIP。
+/// 目标代码按普通函数处理，调用者保存的寄存器和标志位都可能被修改
+pub unsafe fn call_gate<S: Selector>(selector: S) {
+    /// m16:32格式的远指针，偏移量在前，段选择子在后
+    /// AMD处理器不支持REX.W前缀的m16:64远指针，因此使用所有处理器都支持的m16:32格式
+    #[repr(C, packed)]
+    struct FarPointer {
+        offset: u32,
+        selector: u16,
+    }
+
+    let far_pointer = FarPointer { offset: 0, selector: selector.as_u16() };
+    llvm_asm!(
+        "lcall *($0)"
+        :
+        :"r"(&far_pointer)
+        :"rax", "rcx", "rdx", "rsi", "rdi", "r8", "r9", "r10", "r11", "cc", "memory"
+        :"volatile"
+    );
+}
@@ -1,6 +1,6 @@
-///! 用于加载GDT IDT TSS的相关指令
+///! 用于加载GDT IDT TSS LDT的相关指令
 use crate::arch::intel::{IntelX64, Selector};
-use crate::arch::intel::x64::DescriptorTablePointer;
+use crate::arch::intel::x64::{DescriptorTablePointer, SegmentSelector};
 
 /// 使用`lgdt`加载GDT描述符
 #[inline]
@@ -48,4 +48,20 @@ pub unsafe fn load_tss<T: Selector>(sel: T) {
 #[inline]
 pub unsafe fn load_tr<T: Selector>(sel: T) {
     llvm_asm!("ltr $0" :: "r" (sel.as_u16()));
-}
\ No newline at end of file
+}
+/// 使用`lldt`加载LDT描述符，选择子必须指向GDT中的LDT描述符，加载空选择子表示不使用LDT
+#[inline]
+pub unsafe fn lldt<T: Selector>(sel: T) {
+    llvm_asm!("lldt $0" :: "r" (sel.as_u16()) : "memory");
+}
+
+/// 使用`sldt`取出LDTR寄存器中的段选择子
+#[allow(unused_assignments)]
+#[inline]
+pub fn sldt() -> SegmentSelector {
+    let mut sel: u16 = 0;
+    unsafe {
+        llvm_asm!("sldt $0" : "=r"(sel));
+    }
+    SegmentSelector(sel)
+}
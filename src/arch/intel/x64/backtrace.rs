@@ -0,0 +1,218 @@
+///! 基于帧指针的栈回溯
+///! 内核需要使用`-C force-frame-pointers=yes`编译，每个栈帧以`[rbp] = 上一帧的rbp`，`[rbp + 8] = 返回地址`开始。
+///! 回溯过程中只读取已映射的地址，并使用内核ELF文件的`.symtab`将返回地址转为`函数名+偏移量`，
+///! 符号表需要在启动时通过`set_kernel_symbols`加载（会分配内存），之后`FrameIter`、`symbolize`和`write_backtrace`
+///! 都不分配内存，因此可以在panic和异常处理函数中使用
+use alloc::vec::Vec;
+use core::fmt;
+
+use spin::RwLock;
+use xmas_elf::ElfFile;
+use xmas_elf::sections::SectionData;
+use xmas_elf::symbol_table::{Entry, Type};
+
+use crate::arch::intel::call_convention::InterruptStack;
+use crate::arch::intel::instructions::register::{read_rbp, read_rip};
+use crate::arch::intel::x64::address::{VirtAddr, VirtualAddress};
+use crate::arch::intel::x64::paging::mapper::MapAllSize;
+
+/// 最多回溯的栈帧数，防止损坏的栈导致死循环
+pub const MAX_FRAMES: usize = 64;
+
+static KERNEL_SYMBOLS: RwLock<Option<SymbolTable<'static>>> = RwLock::new(None);
+
+/// 函数符号
+#[derive(Debug, Copy, Clone)]
+struct Symbol<'a> {
+    name: &'a str,
+    start: u64,
+    size: u64,
+}
+
+/// 按地址排序的函数符号表
+pub struct SymbolTable<'a> {
+    symbols: Vec<Symbol<'a>>,
+}
+
+impl<'a> SymbolTable<'a> {
+    /// 从ELF文件的`.symtab`中读取所有函数符号，没有符号表时返回空表
+    pub fn from_elf(data: &'a [u8]) -> Result<Self, &'static str> {
+        let elf = ElfFile::new(data)?;
+        let mut symbols = Vec::new();
+        for section in elf.section_iter() {
+            let entries = match section.get_data(&elf)? {
+                SectionData::SymbolTable64(entries) => entries,
+                _ => continue,
+            };
+            for entry in entries {
+                if entry.get_type()? != Type::Func || entry.value() == 0 {
+                    continue;
+                }
+                symbols.push(Symbol { name: entry.get_name(&elf)?, start: entry.value(), size: entry.size() });
+            }
+        }
+        symbols.sort_unstable_by_key(|symbol| symbol.start);
+        Ok(Self { symbols })
+    }
+
+    /// 查找包含`addr`的函数，返回函数名和地址在函数内的偏移量
+    /// 大小为0的符号（例如汇编函数）视为延伸到下一个符号
+    pub fn lookup(&self, addr: VirtAddr) -> Option<(&'a str, u64)> {
+        let addr = addr.as_u64();
+        let index = match self.symbols.binary_search_by_key(&addr, |symbol| symbol.start) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+        let symbol = self.symbols[index];
+        let offset = addr - symbol.start;
+        if symbol.size != 0 && offset >= symbol.size {
+            return None;
+        }
+        Some((symbol.name, offset))
+    }
+
+    /// 符号个数
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+}
+
+/// 注册内核ELF文件的符号表，之后的回溯都使用该符号表
+pub fn set_kernel_symbols(data: &'static [u8]) -> Result<(), &'static str> {
+    let table = SymbolTable::from_elf(data)?;
+    *KERNEL_SYMBOLS.write() = Some(table);
+    Ok(())
+}
+
+/// 使用内核符号表查找地址，符号表正在被修改时返回None以避免在panic中死锁
+pub fn symbolize(addr: VirtAddr) -> Option<(&'static str, u64)> {
+    KERNEL_SYMBOLS.try_read()?.as_ref()?.lookup(addr)
+}
+
+/// 一个栈帧
+#[derive(Debug, Copy, Clone)]
+pub struct StackFrame {
+    /// 栈帧的rbp，第一个栈帧（异常发生的位置）为异常时的rbp
+    pub rbp: VirtAddr,
+    /// 返回地址，第一个栈帧为异常时的rip
+    pub rip: VirtAddr,
+}
+
+impl fmt::Display for StackFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // 返回地址指向call的下一条指令，减1后查找以正确处理以noreturn函数调用结束的函数
+        let lookup = VirtAddr::new_unchecked(self.rip.as_u64().saturating_sub(1));
+        match symbolize(lookup) {
+            Some((name, offset)) => write!(f, "{:#018x} {}+{:#x}", self.rip.as_u64(), name, offset + 1),
+            None => write!(f, "{:#018x} <unknown>", self.rip.as_u64()),
+        }
+    }
+}
+
+/// 沿着rbp链遍历栈帧
+/// 读取每一帧前都通过`mapper`检查`[rbp, rbp + 16)`已映射，rbp必须按8字节对齐并严格递增
+pub struct FrameIter<'a, M: MapAllSize> {
+    mapper: &'a M,
+    first: Option<StackFrame>,
+    rbp: u64,
+    depth: usize,
+}
+
+impl<'a, M: MapAllSize> FrameIter<'a, M> {
+    /// 从给定的rip和rbp开始回溯
+    ///
+    /// # Safety
+    /// `mapper`必须是当前使用的页表
+    pub unsafe fn new(mapper: &'a M, rip: VirtAddr, rbp: VirtAddr) -> Self {
+        Self {
+            mapper,
+            first: Some(StackFrame { rbp, rip }),
+            rbp: rbp.as_u64(),
+            depth: 0,
+        }
+    }
+
+    /// 从当前函数开始回溯
+    ///
+    /// # Safety
+    /// `mapper`必须是当前使用的页表
+    #[inline(always)]
+    pub unsafe fn current(mapper: &'a M) -> Self {
+        Self::new(mapper, VirtAddr::new_unchecked(read_rip()), VirtAddr::new_unchecked(read_rbp()))
+    }
+
+    /// 从异常发生的位置开始回溯，异常处理函数需要使用`interrupt_frame!`保存rbp
+    /// 异常时的rip不是Canonical地址时不返回任何栈帧；rbp不是Canonical地址时只返回第一个栈帧，其rbp为0
+    ///
+    /// # Safety
+    /// `mapper`必须是当前使用的页表
+    pub unsafe fn from_interrupt(mapper: &'a M, stack: &InterruptStack) -> Self {
+        let rip = canonical(stack.iret.raw_rip());
+        let rbp = canonical(stack.preserved.raw_rbp());
+        Self {
+            mapper,
+            first: rip.map(|rip| StackFrame { rbp: rbp.unwrap_or_else(VirtAddr::zero), rip }),
+            rbp: rbp.filter(|_| rip.is_some()).map_or(0, |rbp| rbp.as_u64()),
+            depth: 0,
+        }
+    }
+
+    /// 检查地址按8字节对齐且`[addr, addr + 16)`已映射
+    fn readable(&self, addr: u64) -> bool {
+        if addr == 0 || addr % 8 != 0 {
+            return false;
+        }
+        let end = match addr.checked_add(15) {
+            Some(end) => end,
+            None => return false,
+        };
+        match (VirtAddr::try_new(addr), VirtAddr::try_new(end)) {
+            (Ok(start), Ok(end)) => self.mapper.translate_addr(start).is_some() && self.mapper.translate_addr(end).is_some(),
+            _ => false,
+        }
+    }
+}
+
+impl<'a, M: MapAllSize> Iterator for FrameIter<'a, M> {
+    type Item = StackFrame;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(first) = self.first.take() {
+            return Some(first);
+        }
+        if self.depth >= MAX_FRAMES || !self.readable(self.rbp) {
+            return None;
+        }
+        let frame = self.rbp as *const u64;
+        let (caller_rbp, rip) = unsafe { (frame.read(), frame.add(1).read()) };
+        if rip == 0 {
+            return None;
+        }
+        let rip = canonical(rip)?;
+        let current = self.rbp;
+        // 调用者的栈帧地址更高，不满足时说明栈已损坏或到达最外层
+        self.rbp = if caller_rbp > current { caller_rbp } else { 0 };
+        self.depth += 1;
+        Some(StackFrame { rbp: VirtAddr::new_unchecked(current), rip })
+    }
+}
+
+/// `addr`是Canonical地址时返回对应的`VirtAddr`
+/// `VirtAddr::try_new`会对只有第47位置位的地址做符号扩展，这里要求地址本身就是Canonical的
+fn canonical(addr: u64) -> Option<VirtAddr> {
+    VirtAddr::try_new(addr).ok().filter(|virt| virt.as_u64() == addr)
+}
+
+/// 将回溯结果逐行写入`w`，格式为`#序号 地址 函数名+偏移量`
+pub fn write_backtrace<W, I>(w: &mut W, frames: I) -> fmt::Result where W: fmt::Write, I: Iterator<Item=StackFrame> {
+    writeln!(w, "backtrace:")?;
+    for (index, frame) in frames.enumerate() {
+        writeln!(w, "  #{:<2} {}", index, frame)?;
+    }
+    Ok(())
+}
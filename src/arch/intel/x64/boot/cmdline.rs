@@ -0,0 +1,108 @@
+///! 内核命令行解析
+///! 命令行由空白分隔的参数组成，参数可以是`key=value`或单独的标志，值可以用双引号包含空白，例如
+///! `root=/dev/sda1 quiet console="ttyS0 115200"`
+use alloc::string::String;
+use core::str::CharIndices;
+
+/// 命令行参数
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Argument<'a> {
+    /// 不带值的标志
+    Flag(&'a str),
+    /// `key=value`形式的参数，值已去掉双引号
+    Value(&'a str, &'a str),
+}
+
+impl<'a> Argument<'a> {
+    /// 参数名
+    pub fn key(&self) -> &'a str {
+        match *self {
+            Argument::Flag(key) | Argument::Value(key, _) => key,
+        }
+    }
+}
+
+/// 内核命令行
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct CommandLine {
+    raw: String,
+}
+
+impl CommandLine {
+    pub fn new(raw: &str) -> Self {
+        Self { raw: String::from(raw) }
+    }
+
+    /// 原始的命令行字符串
+    pub fn raw(&self) -> &str {
+        &self.raw
+    }
+
+    /// 遍历所有参数
+    pub fn iter(&self) -> ArgumentIter<'_> {
+        ArgumentIter::new(&self.raw)
+    }
+
+    /// 返回参数`key`的值，同名参数出现多次时以最后一次为准
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.iter().filter_map(|arg| match arg {
+            Argument::Value(k, value) if k == key => Some(value),
+            _ => None,
+        }).last()
+    }
+
+    /// 是否存在名为`key`的参数，无论是否带值
+    pub fn contains(&self, key: &str) -> bool {
+        self.iter().any(|arg| arg.key() == key)
+    }
+}
+
+/// 按顺序解析命令行参数，未闭合的双引号延伸到命令行末尾
+pub struct ArgumentIter<'a> {
+    raw: &'a str,
+    chars: CharIndices<'a>,
+}
+
+impl<'a> ArgumentIter<'a> {
+    pub fn new(raw: &'a str) -> Self {
+        Self { raw, chars: raw.char_indices() }
+    }
+}
+
+impl<'a> Iterator for ArgumentIter<'a> {
+    type Item = Argument<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (start, first) = loop {
+            let (index, c) = self.chars.next()?;
+            if !c.is_whitespace() {
+                break (index, c);
+            }
+        };
+        let mut end = self.raw.len();
+        let mut equal = None;
+        let mut quoted = first == '"';
+        for (index, c) in &mut self.chars {
+            match c {
+                '"' => quoted = !quoted,
+                '=' if equal.is_none() && !quoted => equal = Some(index),
+                c if c.is_whitespace() && !quoted => {
+                    end = index;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let arg = &self.raw[start..end];
+        Some(match equal {
+            Some(equal) => Argument::Value(&self.raw[start..equal], unquote(&self.raw[equal + 1..end])),
+            None => Argument::Flag(unquote(arg)),
+        })
+    }
+}
+
+/// 去掉首尾的双引号
+fn unquote(value: &str) -> &str {
+    let value = value.strip_prefix('"').unwrap_or(value);
+    value.strip_suffix('"').unwrap_or(value)
+}
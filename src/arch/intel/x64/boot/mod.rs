@@ -0,0 +1,318 @@
+///! 统一的启动信息
+///! 启动时从multiboot2启动信息中一次性提取命令行、启动模块、帧缓冲区、内核ELF节、RSDP和内存布局，
+///! 之后内核的其他部分只需要使用`BootInfo`，不需要再次解析multiboot2标签
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ops::Range;
+use core::slice;
+
+use multiboot2::BootInformation;
+
+use crate::arch::intel::x64::address::{align_up, PhysAddr, PhysicalAddress};
+use crate::arch::intel::x64::memory::MemorySpace;
+
+pub use cmdline::{Argument, ArgumentIter, CommandLine};
+
+pub mod cmdline;
+
+/// 帧缓冲区标签的类型
+const FRAMEBUFFER_TAG: u32 = 8;
+/// ACPI 1.0 RSDP标签的类型
+const RSDP_V1_TAG: u32 = 14;
+/// ACPI 2.0 RSDP标签的类型
+const RSDP_V2_TAG: u32 = 15;
+/// 结束标签的类型
+const END_TAG: u32 = 0;
+
+/// multiboot2标签头
+#[repr(C)]
+pub(crate) struct TagHeader {
+    pub(crate) ty: u32,
+    pub(crate) size: u32,
+}
+
+/// 遍历启动信息中的所有标签，返回标签的类型、起始地址和大小
+pub(crate) struct TagIter {
+    current: usize,
+    end: usize,
+}
+
+impl TagIter {
+    /// # Safety
+    /// `info`必须指向有效的multiboot2启动信息
+    pub(crate) unsafe fn new(info: &BootInformation) -> Self {
+        // 启动信息以8字节的total_size和reserved开始，之后每个标签按8字节对齐
+        Self { current: info.start_address() + 8, end: info.end_address() }
+    }
+}
+
+impl Iterator for TagIter {
+    type Item = (u32, usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.current + size_of::<TagHeader>() > self.end {
+            return None;
+        }
+        let header = unsafe { &*(self.current as *const TagHeader) };
+        if header.ty == END_TAG || (header.size as usize) < size_of::<TagHeader>() {
+            return None;
+        }
+        let tag = (header.ty, self.current, header.size as usize);
+        self.current = align_up((self.current + header.size as usize) as u64, 8) as usize;
+        Some(tag)
+    }
+}
+
+/// 启动模块
+#[derive(Debug, Clone)]
+pub struct BootModule {
+    pub start: PhysAddr,
+    /// 模块的结束地址（不包含）
+    pub end: PhysAddr,
+    /// 引导程序传入的模块命令行
+    pub cmdline: String,
+}
+
+impl BootModule {
+    pub fn size(&self) -> u64 {
+        self.end.as_u64() - self.start.as_u64()
+    }
+}
+
+/// RGB帧缓冲区中一种颜色所在的位
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ColorField {
+    pub position: u8,
+    pub size: u8,
+}
+
+/// 帧缓冲区的类型
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum FramebufferKind {
+    /// 使用调色板，调色板保留在启动信息中
+    Indexed { palette_address: usize, palette_len: u16 },
+    /// 直接颜色
+    Rgb { red: ColorField, green: ColorField, blue: ColorField },
+    /// EGA文本模式，宽高以字符为单位
+    Text,
+}
+
+/// 帧缓冲区
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct FramebufferInfo {
+    pub address: PhysAddr,
+    /// 每行的字节数
+    pub pitch: u32,
+    pub width: u32,
+    pub height: u32,
+    /// 每个像素的位数
+    pub bpp: u8,
+    pub kind: FramebufferKind,
+}
+
+/// 帧缓冲区标签，后面紧跟颜色信息
+#[repr(C, packed)]
+struct RawFramebufferTag {
+    header: TagHeader,
+    address: u64,
+    pitch: u32,
+    width: u32,
+    height: u32,
+    bpp: u8,
+    ty: u8,
+    reserved: u16,
+}
+
+/// 内核ELF节
+#[derive(Debug, Clone)]
+pub struct KernelSection {
+    pub name: String,
+    pub start: u64,
+    /// 节的结束地址（不包含）
+    pub end: u64,
+    /// 原始的`sh_flags`
+    pub flags: u64,
+    /// 是否需要加载到内存中
+    pub allocated: bool,
+}
+
+/// 引导程序复制的RSDP
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Rsdp {
+    pub oem_id: [u8; 6],
+    /// 0表示ACPI 1.0，2表示ACPI 2.0及以上
+    pub revision: u8,
+    pub rsdt_address: u32,
+    /// ACPI 2.0及以上才有XSDT
+    pub xsdt_address: Option<u64>,
+}
+
+impl Rsdp {
+    /// 首选的根表地址，存在XSDT时使用XSDT
+    pub fn root_table(&self) -> PhysAddr {
+        match self.xsdt_address {
+            Some(xsdt) => PhysAddr::new(xsdt),
+            None => PhysAddr::new(u64::from(self.rsdt_address)),
+        }
+    }
+}
+
+/// ACPI 2.0的RSDP，ACPI 1.0只有前20个字节
+#[repr(C, packed)]
+struct RawRsdp {
+    signature: [u8; 8],
+    checksum: u8,
+    oem_id: [u8; 6],
+    revision: u8,
+    rsdt_address: u32,
+    length: u32,
+    xsdt_address: u64,
+    extended_checksum: u8,
+    reserved: [u8; 3],
+}
+
+/// ACPI 1.0 RSDP的大小
+const RSDP_V1_SIZE: usize = 20;
+
+/// 从multiboot2启动信息中提取的启动数据
+#[derive(Debug)]
+pub struct BootInfo {
+    pub cmdline: CommandLine,
+    pub bootloader_name: Option<String>,
+    pub modules: Vec<BootModule>,
+    pub framebuffer: Option<FramebufferInfo>,
+    pub kernel_sections: Vec<KernelSection>,
+    pub rsdp: Option<Rsdp>,
+    /// 已保留内核、启动模块和启动信息的内存布局
+    pub memory: MemorySpace,
+    /// 启动信息结构本身所在的物理地址范围，提取完成后可以回收
+    pub info_range: Range<u64>,
+}
+
+impl BootInfo {
+    /// 从multiboot2启动信息的地址创建，内核ELF段的物理地址与虚拟地址相同
+    ///
+    /// # Safety
+    /// `addr`必须指向引导程序传入的有效multiboot2启动信息
+    pub unsafe fn from_multiboot2(addr: usize) -> Self {
+        Self::from_multiboot2_with_offset(addr, 0)
+    }
+
+    /// 从multiboot2启动信息的地址创建，内核ELF段的物理地址为虚拟地址减去`kernel_offset`
+    ///
+    /// # Safety
+    /// `addr`必须指向引导程序传入的有效multiboot2启动信息
+    pub unsafe fn from_multiboot2_with_offset(addr: usize, kernel_offset: u64) -> Self {
+        let info = multiboot2::load(addr);
+        let cmdline = info.command_line_tag()
+            .map(|tag| CommandLine::new(tag.command_line()))
+            .unwrap_or_default();
+        let bootloader_name = info.boot_loader_name_tag().map(|tag| String::from(tag.name()));
+        let modules = info.module_tags().map(|module| BootModule {
+            start: PhysAddr::new(u64::from(module.start_address())),
+            end: PhysAddr::new(u64::from(module.end_address())),
+            cmdline: String::from(module.name()),
+        }).collect();
+        let kernel_sections = info.elf_sections_tag().map(|tag| {
+            tag.sections().map(|section| KernelSection {
+                name: String::from(section.name()),
+                start: section.start_address(),
+                end: section.end_address(),
+                flags: section.flags().bits(),
+                allocated: section.is_allocated(),
+            }).collect()
+        }).unwrap_or_default();
+
+        let mut framebuffer = None;
+        let mut rsdp = None;
+        for (ty, tag, size) in TagIter::new(&info) {
+            match ty {
+                FRAMEBUFFER_TAG => framebuffer = parse_framebuffer(tag, size),
+                // ACPI 2.0的RSDP优先
+                RSDP_V1_TAG if rsdp.is_none() => rsdp = parse_rsdp(tag, size),
+                RSDP_V2_TAG => rsdp = parse_rsdp(tag, size).or(rsdp),
+                _ => {}
+            }
+        }
+
+        Self {
+            cmdline,
+            bootloader_name,
+            modules,
+            framebuffer,
+            kernel_sections,
+            rsdp,
+            memory: MemorySpace::from_multiboot2_with_offset(&info, kernel_offset),
+            info_range: info.start_address() as u64..info.end_address() as u64,
+        }
+    }
+
+    /// 查找命令行以`name`开头的启动模块
+    pub fn module(&self, name: &str) -> Option<&BootModule> {
+        self.modules.iter().find(|module| module.cmdline.split_whitespace().next() == Some(name))
+    }
+}
+
+unsafe fn parse_framebuffer(tag: usize, size: usize) -> Option<FramebufferInfo> {
+    if size < size_of::<RawFramebufferTag>() {
+        return None;
+    }
+    let raw = &*(tag as *const RawFramebufferTag);
+    let color = tag + size_of::<RawFramebufferTag>();
+    let kind = match raw.ty {
+        0 if size >= size_of::<RawFramebufferTag>() + 2 => FramebufferKind::Indexed {
+            palette_len: (color as *const u16).read_unaligned(),
+            palette_address: color + 2,
+        },
+        1 if size >= size_of::<RawFramebufferTag>() + 6 => {
+            let field = |index: usize| ColorField {
+                position: *((color + index * 2) as *const u8),
+                size: *((color + index * 2 + 1) as *const u8),
+            };
+            FramebufferKind::Rgb { red: field(0), green: field(1), blue: field(2) }
+        }
+        2 => FramebufferKind::Text,
+        _ => return None,
+    };
+    Some(FramebufferInfo {
+        address: PhysAddr::new(raw.address),
+        pitch: raw.pitch,
+        width: raw.width,
+        height: raw.height,
+        bpp: raw.bpp,
+        kind,
+    })
+}
+
+/// 解析标签中的RSDP，签名或校验和错误时返回None
+unsafe fn parse_rsdp(tag: usize, size: usize) -> Option<Rsdp> {
+    let data = tag + size_of::<TagHeader>();
+    let len = size - size_of::<TagHeader>();
+    if len < RSDP_V1_SIZE {
+        return None;
+    }
+    let bytes = slice::from_raw_parts(data as *const u8, len);
+    let checksum = |bytes: &[u8]| bytes.iter().fold(0_u8, |sum, b| sum.wrapping_add(*b)) == 0;
+    if &bytes[..8] != b"RSD PTR " || !checksum(&bytes[..RSDP_V1_SIZE]) {
+        return None;
+    }
+    // ACPI 1.0的RSDP只有20个字节，不足的部分补0
+    let mut raw = [0_u8; size_of::<RawRsdp>()];
+    let copied = len.min(raw.len());
+    raw[..copied].copy_from_slice(&bytes[..copied]);
+    let rsdp = (raw.as_ptr() as *const RawRsdp).read_unaligned();
+    let mut result = Rsdp {
+        oem_id: rsdp.oem_id,
+        revision: rsdp.revision,
+        rsdt_address: rsdp.rsdt_address,
+        xsdt_address: None,
+    };
+    if rsdp.revision >= 2 && len >= size_of::<RawRsdp>() {
+        let length = (rsdp.length as usize).min(len);
+        if checksum(&bytes[..length]) {
+            result.xsdt_address = Some(rsdp.xsdt_address);
+        }
+    }
+    Some(result)
+}
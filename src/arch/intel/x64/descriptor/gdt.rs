@@ -1,10 +1,20 @@
 use core::fmt;
+use core::mem::size_of;
 
-use crate::arch::intel::{IntelX64, PrivilegedLevel};
+use bit_field::BitField;
+
+use crate::arch::intel::{IntelX64, PrivilegedLevel, Selector};
 use crate::arch::intel::x64::{DescriptorTablePointer, SegmentSelector};
+use crate::arch::intel::x64::address::VirtAddr;
 use crate::arch::intel::x64::descriptor::Descriptor;
 use crate::arch::intel::x64::descriptor::flags::{GdtAccessFlags, GdtFlags};
+use crate::arch::intel::x64::descriptor::tss::{TaskStateSegment, TaskStateSegmentWithIoBitmap};
 
+/// 传统的8字节段描述符
+/// | 63-56     |55|54 |53|52 |51-48   |47|46-45|44|43-40|39-16       |15-0      |
+/// +-----------+--+---+--+---+--------+--+-----+--+-----+------------+----------+
+/// |BaseAddr(H)|G |D/B|L |AVL|limit(H)|P |DPL  |S |Type | BaseAddr(L)| limit(L) |
+/// +-----------+--+---+--+---+--------+--+-----+--+-----+------------+----------+
 #[derive(Copy, Clone, Debug)]
 #[repr(packed)]
 pub struct GdtEntry {
@@ -29,17 +39,334 @@ impl GdtEntry {
         }
     }
 
+    /// 从GDT中的原始描述符创建
+    pub fn from_u64(value: u64) -> Self {
+        GdtEntry {
+            limit_low: value.get_bits(0..16) as u16,
+            offset_low: value.get_bits(16..32) as u16,
+            offset_mid: value.get_bits(32..40) as u8,
+            access: value.get_bits(40..48) as u8,
+            flags_limit_high: value.get_bits(48..56) as u8,
+            offset_high: value.get_bits(56..64) as u8,
+        }
+    }
+
+    /// 转为GDT中的原始描述符
+    pub fn as_u64(&self) -> u64 {
+        let mut value = 0_u64;
+        value.set_bits(0..16, u64::from(self.limit_low));
+        value.set_bits(16..32, u64::from(self.offset_low));
+        value.set_bits(32..40, u64::from(self.offset_mid));
+        value.set_bits(40..48, u64::from(self.access));
+        value.set_bits(48..56, u64::from(self.flags_limit_high));
+        value.set_bits(56..64, u64::from(self.offset_high));
+        value
+    }
+
+    /// 段基址
+    pub fn base(&self) -> u32 {
+        u32::from(self.offset_low) | u32::from(self.offset_mid) << 16 | u32::from(self.offset_high) << 24
+    }
+
+    /// 20位的原始段限长
+    pub fn limit(&self) -> u32 {
+        u32::from(self.limit_low) | u32::from(self.flags_limit_high & 0x0F) << 16
+    }
+
+    /// 以字节为单位的段限长，设置了`PAGE_SIZE`（G位）时以4KB为单位
+    pub fn byte_limit(&self) -> u64 {
+        let limit = u64::from(self.limit());
+        if self.flags().contains(GdtFlags::PAGE_SIZE) {
+            limit << 12 | 0xFFF
+        } else {
+            limit
+        }
+    }
+
+    pub fn access(&self) -> GdtAccessFlags {
+        GdtAccessFlags::from_bits_truncate(self.access)
+    }
+
+    pub fn flags(&self) -> GdtFlags {
+        GdtFlags::from_bits_truncate(self.flags_limit_high & 0xF0)
+    }
+
+    /// 描述符特权级（DPL），位于access的5-6位
     pub fn privileged_level(&self) -> PrivilegedLevel {
-        let flags = GdtAccessFlags::from_bits_truncate(self.access);
-        return if flags.contains(GdtAccessFlags::RING_0) {
-            PrivilegedLevel::Ring0
-        } else if flags.contains(GdtAccessFlags::RING_1) {
-            PrivilegedLevel::Ring1
-        } else if flags.contains(GdtAccessFlags::RING_1) {
-            PrivilegedLevel::Ring2
+        PrivilegedLevel::from_u16(u16::from(self.access.get_bits(5..7)))
+    }
+}
+
+impl From<GdtEntry> for u64 {
+    fn from(entry: GdtEntry) -> Self {
+        entry.as_u64()
+    }
+}
+
+/// 代码段和数据段描述符的构造器
+///
+/// ```
+/// use libarch::arch::intel::PrivilegedLevel;
+/// use libarch::arch::intel::x64::descriptor::gdt::SegmentBuilder;
+///
+/// // 基址为0，段限长为4GB的32位兼容模式用户代码段
+/// let entry = SegmentBuilder::code().byte_limit(0xFFFF_FFFF).default_32bit().dpl(PrivilegedLevel::Ring3).build();
+/// ```
+#[derive(Debug, Copy, Clone)]
+pub struct SegmentBuilder {
+    base: u32,
+    limit: u32,
+    access: GdtAccessFlags,
+    flags: GdtFlags,
+}
+
+impl SegmentBuilder {
+    /// 可读的代码段，特权级0
+    pub fn code() -> Self {
+        Self {
+            base: 0,
+            limit: 0,
+            access: GdtAccessFlags::PRESENT | GdtAccessFlags::SYSTEM | GdtAccessFlags::EXECUTABLE | GdtAccessFlags::PRIVILEGE,
+            flags: GdtFlags::empty(),
+        }
+    }
+
+    /// 可写的数据段，特权级0
+    pub fn data() -> Self {
+        Self {
+            base: 0,
+            limit: 0,
+            access: GdtAccessFlags::PRESENT | GdtAccessFlags::SYSTEM | GdtAccessFlags::PRIVILEGE,
+            flags: GdtFlags::empty(),
+        }
+    }
+
+    pub fn base(mut self, base: u32) -> Self {
+        self.base = base;
+        self
+    }
+
+    /// 设置20位的原始段限长，单位由`page_granularity`决定
+    pub fn limit(mut self, limit: u32) -> Self {
+        assert!(limit <= 0xF_FFFF, "segment limit must fit in 20 bits");
+        self.limit = limit;
+        self
+    }
+
+    /// 段限长以4KB为单位（G位）
+    pub fn page_granularity(mut self) -> Self {
+        self.flags.insert(GdtFlags::PAGE_SIZE);
+        self
+    }
+
+    /// 以字节为单位设置段限长，超过1MB时自动以4KB为单位，此时段限长向上取整到4KB边界
+    pub fn byte_limit(self, limit: u32) -> Self {
+        if limit <= 0xF_FFFF {
+            self.limit(limit)
         } else {
-            PrivilegedLevel::Ring3
+            self.limit(limit >> 12).page_granularity()
+        }
+    }
+
+    pub fn dpl(mut self, level: PrivilegedLevel) -> Self {
+        self.access.remove(GdtAccessFlags::RING_3);
+        self.access.insert(GdtAccessFlags::from_bits_truncate((level as u8) << 5));
+        self
+    }
+
+    /// 默认操作数大小为32位（D/B位），与`long_mode`互斥
+    pub fn default_32bit(mut self) -> Self {
+        self.flags.remove(GdtFlags::LONG_MODE);
+        self.flags.insert(GdtFlags::PROTECTED_MODE);
+        self
+    }
+
+    /// 64位代码段（L位），与`default_32bit`互斥
+    pub fn long_mode(mut self) -> Self {
+        assert!(self.access.contains(GdtAccessFlags::EXECUTABLE), "only code segments can be long mode");
+        self.flags.remove(GdtFlags::PROTECTED_MODE);
+        self.flags.insert(GdtFlags::LONG_MODE);
+        self
+    }
+
+    /// 一致性代码段，或向下扩展的数据段
+    pub fn conforming(mut self) -> Self {
+        self.access.insert(GdtAccessFlags::CONFORMING);
+        self
+    }
+
+    /// 只执行的代码段，或只读的数据段
+    pub fn read_only(mut self) -> Self {
+        self.access.remove(GdtAccessFlags::PRIVILEGE);
+        self
+    }
+
+    pub fn build(self) -> GdtEntry {
+        GdtEntry::new(self.base, self.limit, self.access, self.flags)
+    }
+
+    /// 构造`Descriptor`，特权级为3时为用户段，否则为内核段
+    pub fn descriptor(self) -> Descriptor {
+        let entry = self.build();
+        match entry.privileged_level() {
+            PrivilegedLevel::Ring3 => Descriptor::UserSegment(entry.as_u64()),
+            _ => Descriptor::KernelSegment(entry.as_u64()),
+        }
+    }
+}
+
+/// 系统段描述符类型
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SystemSegmentType {
+    Ldt,
+    TssAvailable,
+    TssBusy,
+    CallGate,
+    InterruptGate,
+    TrapGate,
+    /// 长模式下保留的类型
+    Reserved(u8),
+}
+
+impl SystemSegmentType {
+    pub fn from_u8(ty: u8) -> Self {
+        match ty & 0x0F {
+            0x2 => SystemSegmentType::Ldt,
+            0x9 => SystemSegmentType::TssAvailable,
+            0xB => SystemSegmentType::TssBusy,
+            0xC => SystemSegmentType::CallGate,
+            0xE => SystemSegmentType::InterruptGate,
+            0xF => SystemSegmentType::TrapGate,
+            other => SystemSegmentType::Reserved(other),
+        }
+    }
+
+    /// 长模式下是否为占用2项的16字节描述符
+    pub fn is_wide(&self) -> bool {
+        !matches!(self, SystemSegmentType::Reserved(_))
+    }
+}
+
+/// 解析后的GDT项
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DecodedDescriptor {
+    /// 空描述符
+    Null,
+    /// 代码段，`limit`以字节为单位
+    Code {
+        base: u32,
+        limit: u64,
+        dpl: PrivilegedLevel,
+        present: bool,
+        long_mode: bool,
+        default_32bit: bool,
+        conforming: bool,
+        readable: bool,
+        accessed: bool,
+    },
+    /// 数据段，`limit`以字节为单位
+    Data {
+        base: u32,
+        limit: u64,
+        dpl: PrivilegedLevel,
+        present: bool,
+        big: bool,
+        expand_down: bool,
+        writable: bool,
+        accessed: bool,
+    },
+    /// 64位调用门
+    CallGate {
+        selector: SegmentSelector,
+        offset: u64,
+        dpl: PrivilegedLevel,
+        present: bool,
+    },
+    /// 系统段，16字节描述符的基址包含高32位
+    System {
+        ty: SystemSegmentType,
+        base: u64,
+        limit: u64,
+        dpl: PrivilegedLevel,
+        present: bool,
+    },
+}
+
+impl DecodedDescriptor {
+    /// 解析原始描述符，`high`为16字节系统段描述符的高8字节
+    pub fn decode(low: u64, high: Option<u64>) -> Self {
+        if low == 0 {
+            return DecodedDescriptor::Null;
+        }
+        let entry = GdtEntry::from_u64(low);
+        let access = entry.access();
+        let flags = entry.flags();
+        let (dpl, present) = (entry.privileged_level(), access.contains(GdtAccessFlags::PRESENT));
+        if !access.contains(GdtAccessFlags::SYSTEM) {
+            let ty = SystemSegmentType::from_u8(entry.access);
+            if ty == SystemSegmentType::CallGate {
+                let offset = low.get_bits(0..16) | low.get_bits(48..64) << 16 | high.unwrap_or(0).get_bits(0..32) << 32;
+                let selector = SegmentSelector(low.get_bits(16..32) as u16);
+                return DecodedDescriptor::CallGate { selector, offset, dpl, present };
+            }
+            let mut base = u64::from(entry.base());
+            if ty.is_wide() {
+                base |= high.unwrap_or(0).get_bits(0..32) << 32;
+            }
+            return DecodedDescriptor::System { ty, base, limit: entry.byte_limit(), dpl, present };
+        }
+        if access.contains(GdtAccessFlags::EXECUTABLE) {
+            DecodedDescriptor::Code {
+                base: entry.base(),
+                limit: entry.byte_limit(),
+                dpl,
+                present,
+                long_mode: flags.contains(GdtFlags::LONG_MODE),
+                default_32bit: flags.contains(GdtFlags::PROTECTED_MODE),
+                conforming: access.contains(GdtAccessFlags::CONFORMING),
+                readable: access.contains(GdtAccessFlags::PRIVILEGE),
+                accessed: access.contains(GdtAccessFlags::DIRTY),
+            }
+        } else {
+            DecodedDescriptor::Data {
+                base: entry.base(),
+                limit: entry.byte_limit(),
+                dpl,
+                present,
+                big: flags.contains(GdtFlags::PROTECTED_MODE),
+                expand_down: access.contains(GdtAccessFlags::CONFORMING),
+                writable: access.contains(GdtAccessFlags::PRIVILEGE),
+                accessed: access.contains(GdtAccessFlags::DIRTY),
+            }
+        }
+    }
+}
+
+/// 遍历原始描述符表，16字节的系统段描述符和调用门只返回一次，返回值为描述符的索引和解析结果
+pub struct DescriptorIter<'a> {
+    table: &'a [u64],
+    index: usize,
+}
+
+impl<'a> DescriptorIter<'a> {
+    pub fn new(table: &'a [u64]) -> Self {
+        Self { table, index: 0 }
+    }
+}
+
+impl<'a> Iterator for DescriptorIter<'a> {
+    type Item = (usize, DecodedDescriptor);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.index;
+        let low = *self.table.get(index)?;
+        let descriptor = DecodedDescriptor::decode(low, self.table.get(index + 1).copied());
+        self.index += match descriptor {
+            DecodedDescriptor::System { ty, .. } if ty.is_wide() => 2,
+            DecodedDescriptor::CallGate { .. } => 2,
+            _ => 1,
         };
+        Some((index, descriptor))
     }
 }
 
@@ -53,6 +380,8 @@ impl GdtEntry {
 /// 处理器的CS和SS段寄存器不能加载空段，否则会发生#GP异常，
 /// 其他寄存器可以使用空段选择子初始化
 /// `GlobalDescriptorTable`会自动添加一个空段选择子，不需要手动添加！
+/// 默认的`GlobalDescriptorTable`包含8项，需要更多的TSS、LDT或兼容模式段时使用`SizedGlobalDescriptorTable<N>`，
+/// GDT最多包含8192项
 /// # Example
 ///
 /// ```
@@ -66,53 +395,67 @@ impl GdtEntry {
 ///     gdt.load()
 /// }
 /// ```
+pub type GlobalDescriptorTable = SizedGlobalDescriptorTable<8>;
+
+/// GDT最多包含的描述符个数
+pub const GDT_MAX_ENTRIES: usize = 8192;
+
+/// 包含`N`项的全局描述符表
 #[derive(Clone)]
-pub struct GlobalDescriptorTable {
-    table: [u64; 8],
+pub struct SizedGlobalDescriptorTable<const N: usize> {
+    table: [u64; N],
     next_free: usize,
 }
 
-impl fmt::Debug for GlobalDescriptorTable {
+impl<const N: usize> fmt::Debug for SizedGlobalDescriptorTable<N> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        use super::flags::DescriptorFlags as Flags;
         f.write_str("GlobalDescriptorTable\n")?;
-        for descriptor in self.table.iter() {
-            let flags = Flags::from_bits_truncate(*descriptor);
-            f.write_fmt(format_args!("descriptor: {:#X}\tflags:{:?}\n", descriptor, flags))?;
+        for (index, descriptor) in DescriptorIter::new(&self.table[..self.next_free]) {
+            f.write_fmt(format_args!("[{}] {:#X}\t{:?}\n", index, self.table[index], descriptor))?;
         }
-        f.write_fmt(format_args!("total: {}", self.table.len()))
+        f.write_fmt(format_args!("used: {}/{}", self.next_free, self.table.len()))
     }
 }
 
-impl GlobalDescriptorTable {
+impl<const N: usize> SizedGlobalDescriptorTable<N> {
     /// 用于初始化全局描述符表，`next_free`用于添加的描述符个数
     /// 在初始化时已经添加过一个空段选择子，因此next_free为1
-    pub fn new() -> GlobalDescriptorTable {
-        GlobalDescriptorTable {
-            table: [0; 8],
+    pub fn new() -> Self {
+        assert!(N > 0 && N <= GDT_MAX_ENTRIES, "GDT length must be in 1..=8192");
+        Self {
+            table: [0; N],
             next_free: 1,
         }
     }
 
     pub fn add_entry(&mut self, entry: GdtEntry) -> SegmentSelector {
         let rpl = entry.privileged_level();
-        let e = &entry as *const _ as u64;
-        let index = self.push(e);
+        let index = self.push(entry.as_u64());
         SegmentSelector::new(index as u16, rpl)
     }
 
-    /// 从给定DescriptorTablePointer指针返回GDT结构,GDT项默认为8个
-    pub unsafe fn from_ptr(ptr: DescriptorTablePointer<IntelX64>) -> GlobalDescriptorTable {
-        let table = core::slice::from_raw_parts(ptr.base as *const u64, 8);
-        let mut t = [0_u64; 8];
-        for i in 0..t.len() {
-            t[i] = table[i].clone();
-        }
-        GlobalDescriptorTable {
+    /// 从给定DescriptorTablePointer指针返回GDT结构，最多复制`N`项
+    pub unsafe fn from_ptr(ptr: DescriptorTablePointer<IntelX64>) -> Self {
+        let len = ((ptr.limit as usize + 1) / size_of::<u64>()).min(N);
+        let table = core::slice::from_raw_parts(ptr.base as *const u64, len);
+        let mut t = [0_u64; N];
+        t[..len].copy_from_slice(table);
+        Self {
             table: t,
-            next_free: 1,
+            next_free: len.max(1),
         }
     }
+
+    /// 描述符表的项数
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// 已使用的项数（包括空描述符）
+    pub fn len(&self) -> usize {
+        self.next_free
+    }
+
     /// 用于添加段选择子，如果添加的段选择子超过最大长度将会Panic
     /// 该方法是私有方法，只用于`add_descriptor`函数
     fn push(&mut self, value: u64) -> usize {
@@ -122,16 +465,16 @@ impl GlobalDescriptorTable {
             self.next_free += 1;
             return index;
         }
-        panic!("GDT max descriptor length is 8")
+        panic!("GDT max descriptor length is {}", N)
     }
-    /// 将描述符注册到指定的索引处，SystemSegment描述符占用2项，因此0<index<=6
+    /// 将描述符注册到指定的索引处，SystemSegment描述符占用2项，因此0<index<N-1
     pub fn add_indexed_descriptor(&mut self, index: usize, descr: Descriptor) {
-        assert!(index > 0 && index < 8);
+        assert!(index > 0 && index < N);
         let mut index = index;
         match descr {
             Descriptor::UserSegment(value) | Descriptor::KernelSegment(value) => self.table[index] = value,
             Descriptor::SystemSegment(value_low, value_high) => {
-                assert!(index < 7);
+                assert!(index < N - 1);
                 self.table[index] = value_low;
                 index += 1;
                 self.table[index] = value_high;
@@ -144,6 +487,8 @@ impl GlobalDescriptorTable {
         let index = match descr {
             Descriptor::UserSegment(value) | Descriptor::KernelSegment(value) => self.push(value),
             Descriptor::SystemSegment(value_low, value_hight) => {
+                // 系统段描述符占用2项，空间不足时不能只写入低8字节
+                assert!(self.next_free + 1 < N, "GDT max descriptor length is {}", N);
                 let index = self.push(value_low);
                 self.push(value_hight);
                 index
@@ -152,21 +497,134 @@ impl GlobalDescriptorTable {
         SegmentSelector::new(index as u16, PrivilegedLevel::Ring0)
     }
 
+    /// 添加指向`selector`代码段中`offset`处的64位调用门，返回RPL等于`dpl`的段选择子，可以直接用于远调用
+    pub fn add_call_gate(&mut self, selector: SegmentSelector, offset: VirtAddr, dpl: PrivilegedLevel) -> SegmentSelector {
+        let gate = self.add_descriptor(Descriptor::call_gate(selector, offset, dpl));
+        SegmentSelector::new(gate.index(), dpl)
+    }
+
+    /// 返回指向描述符表的`DescriptorTablePointer`
+    pub fn pointer(&self) -> DescriptorTablePointer<IntelX64> {
+        DescriptorTablePointer {
+            base: self.table.as_ptr() as u64,
+            limit: (N * size_of::<u64>() - 1) as u16,
+            _mark: Default::default(),
+        }
+    }
+
     /// 加载GDT描述符，加载描述符时需要将描述符结构转换为指针的形式
     /// 然后通过`system::ia_32e::instructions::tables::ldgt;`加载GDT
     #[cfg(target_arch = "x86_64")]
     pub fn load(&'static self) {
+        unsafe { self.load_unchecked() }
+    }
+
+    /// 加载不是`'static`的GDT，例如保存在每个CPU的数据区域中的GDT
+    ///
+    /// # Safety
+    /// 在加载其他GDT之前，该GDT不能被移动、释放或被其他CPU修改，
+    /// 否则处理器加载段选择子时会读取到无效的描述符
+    #[cfg(target_arch = "x86_64")]
+    pub unsafe fn load_unchecked(&self) {
         use crate::arch::intel::instructions::tables::ldgt;
-        use core::mem::size_of;
+        ldgt(&self.pointer());
+    }
+}
 
-        let ptr = DescriptorTablePointer {
-            base: self.table.as_ptr() as u64,
-            limit: (self.table.len() * size_of::<u64>() - 1) as u16,
-            _mark: Default::default(),
-        };
+/// 每个CPU使用的GDT，包含内核代码段、内核数据段、用户数据段、用户代码段和该CPU的TSS
+/// 用户数据段位于用户代码段之前，满足`sysret`对段选择子顺序的要求
+#[derive(Debug, Clone)]
+pub struct PerCpuGdt<const N: usize> {
+    gdt: SizedGlobalDescriptorTable<N>,
+    pub kernel_code: SegmentSelector,
+    pub kernel_data: SegmentSelector,
+    pub user_data: SegmentSelector,
+    pub user_code: SegmentSelector,
+    pub tss: SegmentSelector,
+}
+
+impl<const N: usize> PerCpuGdt<N> {
+    /// 为使用`tss`的CPU创建GDT，`N`至少为7，剩余的项可以通过`gdt_mut`添加LDT或其他段
+    ///
+    /// # Safety
+    /// `tss`在GDT被使用期间不能被移动或释放
+    pub unsafe fn new(tss: &TaskStateSegment) -> Self {
+        Self::with_tss_descriptor(Descriptor::tss_segment_unchecked(tss))
+    }
+
+    /// 为使用带有I/O许可位图的`tss`的CPU创建GDT
+    ///
+    /// # Safety
+    /// `tss`在GDT被使用期间不能被移动或释放
+    pub unsafe fn with_io_bitmap(tss: &TaskStateSegmentWithIoBitmap) -> Self {
+        Self::with_tss_descriptor(Descriptor::tss_segment_with_io_bitmap_unchecked(tss))
+    }
 
-        unsafe {
-            ldgt(&ptr);
+    fn with_tss_descriptor(tss: Descriptor) -> Self {
+        assert!(N >= 7, "per-cpu GDT needs at least 7 entries");
+        let mut gdt = SizedGlobalDescriptorTable::new();
+        let kernel_code = gdt.add_descriptor(Descriptor::kernel_code_segment());
+        let kernel_data = gdt.add_descriptor(Descriptor::kernel_data_segment());
+        let user_data = gdt.add_descriptor(Descriptor::user_data_segment());
+        let user_code = gdt.add_descriptor(Descriptor::user_code_segment());
+        let tss = gdt.add_descriptor(tss);
+        Self {
+            gdt,
+            kernel_code,
+            kernel_data,
+            user_data: SegmentSelector::new(user_data.index(), PrivilegedLevel::Ring3),
+            user_code: SegmentSelector::new(user_code.index(), PrivilegedLevel::Ring3),
+            tss,
         }
     }
-}
\ No newline at end of file
+
+    pub fn gdt(&self) -> &SizedGlobalDescriptorTable<N> {
+        &self.gdt
+    }
+
+    pub fn gdt_mut(&mut self) -> &mut SizedGlobalDescriptorTable<N> {
+        &mut self.gdt
+    }
+
+    /// 在当前CPU上加载GDT，重新加载CS、SS和DS段寄存器并加载TSS
+    ///
+    /// # Safety
+    /// 与`SizedGlobalDescriptorTable::load_unchecked`相同，此外同一个TSS只能被一个CPU加载
+    #[cfg(target_arch = "x86_64")]
+    pub unsafe fn load(&self) {
+        use crate::arch::intel::instructions::segmention::{load_ds, load_ss, set_cs};
+        use crate::arch::intel::instructions::tables::load_tss;
+
+        self.gdt.load_unchecked();
+        set_cs(self.kernel_code);
+        load_ss(self.kernel_data);
+        load_ds(self.kernel_data);
+        load_tss(self.tss);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn call_gate_is_iterated_once() {
+        let (low, high) = match Descriptor::call_gate(SegmentSelector(8), VirtAddr::new(0xFFFF_8000_1234_5678), PrivilegedLevel::Ring3) {
+            Descriptor::SystemSegment(low, high) => (low, high),
+            _ => unreachable!(),
+        };
+        let code = match Descriptor::kernel_code_segment() {
+            Descriptor::KernelSegment(code) => code,
+            _ => unreachable!(),
+        };
+        let table = [0, low, high, code];
+        assert!(DescriptorIter::new(&table).map(|(index, _)| index).eq([0, 1, 3].iter().copied()));
+        match DescriptorIter::new(&table).nth(1) {
+            Some((_, DecodedDescriptor::CallGate { selector, offset, .. })) => {
+                assert_eq!(selector.0, 8);
+                assert_eq!(offset, 0xFFFF_8000_1234_5678);
+            }
+            other => panic!("expected call gate, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,166 @@
+use core::fmt;
+use core::mem::size_of;
+
+use crate::arch::intel::{PrivilegedLevel, Selector};
+use crate::arch::intel::x64::SegmentSelector;
+use crate::arch::intel::x64::address::VirtAddr;
+use crate::arch::intel::x64::descriptor::Descriptor;
+use crate::arch::intel::x64::descriptor::gdt::{DecodedDescriptor, DescriptorIter, GdtEntry};
+
+/// LDT最多包含的描述符个数
+pub const LDT_MAX_ENTRIES: usize = 8192;
+
+/// 局部描述符表
+/// LDT本身由GDT中的LDT系统段描述符描述，通过`lldt`加载GDT中的LDT选择子后，
+/// TI=1的段选择子会在LDT中索引段描述符，用于为每个进程提供独立的16位和32位兼容模式代码段和数据段
+/// 与GDT不同，LDT的第0项不是空描述符，可以正常使用
+///
+/// # Example
+///
+/// ```no_run
+/// use libarch::arch::intel::PrivilegedLevel;
+/// use libarch::arch::intel::x64::descriptor::gdt::{GlobalDescriptorTable, SegmentBuilder};
+/// use libarch::arch::intel::x64::descriptor::ldt::{load_ldt, LocalDescriptorTable};
+///
+/// static mut LDT: LocalDescriptorTable<4> = LocalDescriptorTable::new();
+/// static mut GDT: Option<GlobalDescriptorTable> = None;
+///
+/// unsafe {
+///     let _code = LDT.add_entry(SegmentBuilder::code().byte_limit(0xFFFF_FFFF).default_32bit().dpl(PrivilegedLevel::Ring3).build());
+///     let mut gdt = GlobalDescriptorTable::new();
+///     let ldt = gdt.add_descriptor(LDT.descriptor());
+///     // `lldt`在当前加载的GDT中查找LDT描述符，因此必须先加载包含该描述符的GDT
+///     GDT = Some(gdt);
+///     GDT.as_ref().unwrap().load();
+///     load_ldt(ldt);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct LocalDescriptorTable<const N: usize> {
+    table: [u64; N],
+    next_free: usize,
+}
+
+impl<const N: usize> fmt::Debug for LocalDescriptorTable<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("LocalDescriptorTable\n")?;
+        for (index, descriptor) in DescriptorIter::new(&self.table[..self.next_free]) {
+            f.write_fmt(format_args!("[{}] {:#X}\t{:?}\n", index, self.table[index], descriptor))?;
+        }
+        f.write_fmt(format_args!("used: {}/{}", self.next_free, self.table.len()))
+    }
+}
+
+impl<const N: usize> LocalDescriptorTable<N> {
+    /// 创建空的LDT
+    pub const fn new() -> Self {
+        Self {
+            table: [0; N],
+            next_free: 0,
+        }
+    }
+
+    /// 已使用的项数
+    pub fn len(&self) -> usize {
+        self.next_free
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.next_free == 0
+    }
+
+    /// 添加代码段或数据段描述符，返回TI=1且RPL等于DPL的段选择子，超过最大长度时Panic
+    pub fn add_entry(&mut self, entry: GdtEntry) -> SegmentSelector {
+        assert!(self.next_free < N, "LDT max descriptor length is {}", N);
+        let index = self.next_free;
+        self.table[index] = entry.as_u64();
+        self.next_free += 1;
+        SegmentSelector::new_local(index as u16, entry.privileged_level())
+    }
+
+    /// 添加指向`selector`代码段中`offset`处的64位调用门，调用门占用2项，返回TI=1且RPL等于`dpl`的段选择子
+    pub fn add_call_gate(&mut self, selector: SegmentSelector, offset: VirtAddr, dpl: PrivilegedLevel) -> SegmentSelector {
+        assert!(self.next_free + 1 < N, "LDT max descriptor length is {}", N);
+        let index = self.next_free;
+        if let Descriptor::SystemSegment(low, high) = Descriptor::call_gate(selector, offset, dpl) {
+            self.table[index] = low;
+            self.table[index + 1] = high;
+        }
+        self.next_free += 2;
+        SegmentSelector::new_local(index as u16, dpl)
+    }
+
+    /// 将描述符写入指定的索引，返回对应的段选择子
+    pub fn set_entry(&mut self, index: usize, entry: GdtEntry) -> SegmentSelector {
+        assert!(index < N);
+        self.table[index] = entry.as_u64();
+        self.next_free = self.next_free.max(index + 1);
+        SegmentSelector::new_local(index as u16, entry.privileged_level())
+    }
+
+    /// 清除指定的描述符，之后使用该描述符的段选择子会引发#GP
+    pub fn clear_entry(&mut self, index: usize) {
+        assert!(index < N);
+        self.table[index] = 0;
+    }
+
+    /// 解析指定索引处的描述符
+    pub fn entry(&self, index: usize) -> DecodedDescriptor {
+        DecodedDescriptor::decode(self.table[index], self.table.get(index + 1).copied())
+    }
+
+    /// 段选择子指向的描述符，选择子不指向LDT时返回None
+    pub fn entry_of(&self, selector: SegmentSelector) -> Option<DecodedDescriptor> {
+        let index = selector.index() as usize;
+        if !selector.is_local() || index >= N {
+            return None;
+        }
+        Some(self.entry(index))
+    }
+
+    /// LDT的段限长，LDT的长度必须在`1..=LDT_MAX_ENTRIES`之间
+    pub fn limit(&self) -> u16 {
+        assert!(N > 0 && N <= LDT_MAX_ENTRIES, "LDT length must be in 1..=8192");
+        (N * size_of::<u64>() - 1) as u16
+    }
+
+    /// 创建用于添加到GDT的LDT系统段描述符
+    pub fn descriptor(&'static self) -> Descriptor {
+        unsafe { self.descriptor_unchecked() }
+    }
+
+    /// 为不是`'static`的LDT（例如每个进程各自的LDT）创建LDT系统段描述符
+    ///
+    /// # Safety
+    /// 在描述符被使用期间，LDT不能被移动或释放
+    pub unsafe fn descriptor_unchecked(&self) -> Descriptor {
+        Descriptor::ldt_segment(self.table.as_ptr() as u64, self.limit())
+    }
+}
+
+/// 使用`lldt`加载GDT中的LDT描述符
+///
+/// # Safety
+/// `selector`必须指向当前GDT中有效的LDT描述符，在切换到其他LDT之前，LDT不能被移动或释放
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn load_ldt(selector: SegmentSelector) {
+    use crate::arch::intel::instructions::tables::lldt;
+    lldt(selector);
+}
+
+/// 加载空选择子，之后TI=1的段选择子都会引发#GP
+///
+/// # Safety
+/// 段寄存器中不能再使用指向LDT的段选择子
+#[cfg(target_arch = "x86_64")]
+pub unsafe fn unload_ldt() {
+    use crate::arch::intel::instructions::tables::lldt;
+    lldt(SegmentSelector(0));
+}
+
+/// 使用`sldt`获取当前加载的LDT选择子
+#[cfg(target_arch = "x86_64")]
+pub fn current_ldt() -> SegmentSelector {
+    use crate::arch::intel::instructions::tables::sldt;
+    sldt()
+}
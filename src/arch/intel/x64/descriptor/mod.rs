@@ -1,8 +1,12 @@
-use crate::arch::intel::x64::descriptor::tss::TaskStateSegment;
+use crate::arch::intel::PrivilegedLevel;
+use crate::arch::intel::x64::SegmentSelector;
+use crate::arch::intel::x64::address::{VirtAddr, VirtualAddress};
+use crate::arch::intel::x64::descriptor::tss::{TaskStateSegment, TaskStateSegmentWithIoBitmap};
 
 pub mod flags;
 pub mod gdt;
 pub mod idt;
+pub mod ldt;
 pub mod tss;
 
 /// 64位描述符
@@ -82,18 +86,44 @@ impl Descriptor {
     /// +---------+--+--+--+---+-----+--+-----+--+--+--+--+--+--------+--------+-----+
     ///
     pub fn tss_segment(ts: &'static TaskStateSegment) -> Descriptor {
-        use self::flags::DescriptorFlags;
+        unsafe { Self::tss_segment_unchecked(ts) }
+    }
+
+    /// 根据不是`'static`的`TaskStateSegment`（例如每个CPU各自的TSS）创建TSS描述符
+    ///
+    /// # Safety
+    /// 在描述符被使用期间，`ts`不能被移动或释放
+    pub unsafe fn tss_segment_unchecked(ts: &TaskStateSegment) -> Descriptor {
+        use core::mem::size_of;
+        Self::tss_descriptor(ts as *const _ as u64, size_of::<TaskStateSegment>() - 1)
+    }
+
+    /// 根据带有I/O许可位图的TSS创建TSS描述符，段限长包含位图和结束字节
+    pub fn tss_segment_with_io_bitmap(ts: &'static TaskStateSegmentWithIoBitmap) -> Descriptor {
+        unsafe { Self::tss_segment_with_io_bitmap_unchecked(ts) }
+    }
+
+    /// 根据不是`'static`的带有I/O许可位图的TSS创建TSS描述符
+    ///
+    /// # Safety
+    /// 在描述符被使用期间，`ts`不能被移动或释放
+    pub unsafe fn tss_segment_with_io_bitmap_unchecked(ts: &TaskStateSegmentWithIoBitmap) -> Descriptor {
         use core::mem::size_of;
+        Self::tss_descriptor(ts as *const _ as u64, size_of::<TaskStateSegmentWithIoBitmap>() - 1)
+    }
+
+    fn tss_descriptor(ptr: u64, limit: usize) -> Descriptor {
+        use self::flags::DescriptorFlags;
         use bit_field::BitField;
 
-        let ptr = ts as *const _ as u64;
         let mut low = DescriptorFlags::PRESENT.bits();
 
         // 段基址(低)
         low.set_bits(16..40, ptr.get_bits(0..24));
         low.set_bits(56..64, ptr.get_bits(24..32));
-        // 段限长
-        low.set_bits(0..16, (size_of::<TaskStateSegment>() - 1) as u64);
+        // 段限长，高4位位于51-48位
+        low.set_bits(0..16, limit.get_bits(0..16) as u64);
+        low.set_bits(48..52, limit.get_bits(16..20) as u64);
         // 段属性 1001表示64位TSS段描述符
         low.set_bits(40..44, 0b1001);
 
@@ -102,4 +132,57 @@ impl Descriptor {
         high.set_bits(0..32, ptr.get_bits(32..64));
         Descriptor::SystemSegment(low, high)
     }
+
+    /// 根据LDT的基址和段限长创建LDT描述符，LDT描述符与TSS描述符一样占用2项
+    /// | 63 - 56 |55|54|53|52 |51-48|47|46-45|44|43-40| 39 - 16 |15 -0|
+    /// +---------+--+--+--+---+-----+--+-----+--+-----+---------+-----+
+    /// |Base Addr|0 |0 |0 |AVL|Limit|P | DPL |0 |0010 |Base Addr|Limit|
+    /// +---------+--+--+--+---+-----+--+-----+--+-----+---------+-----+
+    pub fn ldt_segment(base: u64, limit: u16) -> Descriptor {
+        use self::flags::DescriptorFlags;
+        use bit_field::BitField;
+
+        let mut low = DescriptorFlags::PRESENT.bits();
+        // 段基址(低)
+        low.set_bits(16..40, base.get_bits(0..24));
+        low.set_bits(56..64, base.get_bits(24..32));
+        // 段限长
+        low.set_bits(0..16, u64::from(limit));
+        // 段属性 0010表示LDT段描述符
+        low.set_bits(40..44, 0b0010);
+
+        let mut high: u64 = 0;
+        // 段基址(高)
+        high.set_bits(0..32, base.get_bits(32..64));
+        Descriptor::SystemSegment(low, high)
+    }
+
+    /// 创建64位调用门描述符，通过`DPL`不小于当前特权级的调用门可以远调用到`selector`指向的代码段中的`offset`处
+    /// IA-32e模式下调用门占用2项，不支持参数复制（没有参数个数字段），也不支持任务门
+    /// | 63 - 48    |47|46-45|44|43-40| 39-32    | 31 - 16  | 15 - 0    |
+    /// +------------+--+-----+--+-----+----------+----------+-----------+
+    /// |Offset(M)   |P | DPL |0 |1100 | reserved | Selector | Offset(L) |
+    /// +------------+--+-----+--+-----+----------+----------+-----------+
+    ///
+    /// |127 - 109   |108 - 104|103 - 96  | 95 - 64   |
+    /// +------------+---------+----------+-----------+
+    /// | reserved   |  00000  | reserved | Offset(H) |
+    /// +------------+---------+----------+-----------+
+    pub fn call_gate(selector: SegmentSelector, offset: VirtAddr, dpl: PrivilegedLevel) -> Descriptor {
+        use self::flags::DescriptorFlags;
+        use bit_field::BitField;
+
+        let offset = offset.as_u64();
+        let mut low = DescriptorFlags::PRESENT.bits();
+        low.set_bits(0..16, offset.get_bits(0..16));
+        low.set_bits(16..32, u64::from(selector.0));
+        low.set_bits(48..64, offset.get_bits(16..32));
+        // 段属性 1100表示64位调用门
+        low.set_bits(40..44, 0b1100);
+        low.set_bits(45..47, dpl as u64);
+
+        let mut high: u64 = 0;
+        high.set_bits(0..32, offset.get_bits(32..64));
+        Descriptor::SystemSegment(low, high)
+    }
 }
\ No newline at end of file
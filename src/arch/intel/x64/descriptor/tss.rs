@@ -1,3 +1,6 @@
+use core::mem::size_of;
+use core::ops::RangeInclusive;
+
 use crate::arch::intel::x64::address::VirtAddr;
 
 /// RSPn： Canonical型栈指针(特权级0-2)
@@ -33,4 +36,68 @@ impl TaskStateSegment {
             reserved_4: 0,
         }
     }
-}
\ No newline at end of file
+}
+
+/// I/O许可位图的字节数，每个端口占用1位，共65536个端口
+pub const IO_BITMAP_SIZE: usize = 65536 / 8;
+
+/// 带有I/O许可位图的TSS
+/// 当CPL大于IOPL时，`in`/`out`等指令会检查位图中对应的位，位为0时允许访问，为1时引发#GP。
+/// 处理器访问多字节端口时可能读取位图之后的1个字节，因此位图后必须有一个全1的结束字节
+#[derive(Debug, Clone)]
+#[repr(C, packed)]
+pub struct TaskStateSegmentWithIoBitmap {
+    pub tss: TaskStateSegment,
+    io_bitmap: [u8; IO_BITMAP_SIZE],
+    terminator: u8,
+}
+
+impl TaskStateSegmentWithIoBitmap {
+    /// 创建禁止访问所有端口的TSS，`io_map_base`指向紧跟在TSS后的位图
+    pub const fn new() -> Self {
+        let mut tss = TaskStateSegment::new();
+        tss.io_map_base = size_of::<TaskStateSegment>() as u16;
+        Self {
+            tss,
+            io_bitmap: [0xFF; IO_BITMAP_SIZE],
+            terminator: 0xFF,
+        }
+    }
+
+    /// 允许访问给定范围内的端口
+    pub fn allow(&mut self, ports: RangeInclusive<u16>) {
+        self.set_range(ports, false);
+    }
+
+    /// 禁止访问给定范围内的端口
+    pub fn deny(&mut self, ports: RangeInclusive<u16>) {
+        self.set_range(ports, true);
+    }
+
+    /// 允许访问所有端口
+    pub fn allow_all(&mut self) {
+        self.io_bitmap = [0; IO_BITMAP_SIZE];
+    }
+
+    /// 禁止访问所有端口
+    pub fn deny_all(&mut self) {
+        self.io_bitmap = [0xFF; IO_BITMAP_SIZE];
+    }
+
+    /// 端口是否允许访问
+    pub fn is_allowed(&self, port: u16) -> bool {
+        let (byte, bit) = (port as usize / 8, port % 8);
+        self.io_bitmap[byte] & (1 << bit) == 0
+    }
+
+    fn set_range(&mut self, ports: RangeInclusive<u16>, deny: bool) {
+        for port in ports {
+            let (byte, bit) = (port as usize / 8, port % 8);
+            if deny {
+                self.io_bitmap[byte] |= 1 << bit;
+            } else {
+                self.io_bitmap[byte] &= !(1 << bit);
+            }
+        }
+    }
+}
@@ -0,0 +1,282 @@
+///! ELF64内核与模块加载器
+///! 校验ELF文件后将所有`PT_LOAD`段映射到给定的页表中，段内容通过物理内存直接映射写入物理帧，
+///! 因此目标页表不需要是当前正在使用的页表
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ptr;
+
+use xmas_elf::ElfFile;
+use xmas_elf::header::{self, Class, Data, Machine};
+use xmas_elf::program::{ProgramHeader, Type};
+
+use crate::arch::intel::chips::msr_set::Efer;
+use crate::arch::intel::x64::address::{align_down, align_up, VirtAddr, VirtualAddress};
+use crate::arch::intel::x64::paging::{FrameAllocator, Page, Page4KB, PageSize, UnusedFrame};
+use crate::arch::intel::x64::paging::flags::PageTableFlags;
+use crate::arch::intel::x64::paging::mapper::{MapAllSize, Mapper, MapperFlushBatch, PhysOffset};
+use crate::arch::intel::x64::paging::result::{FlagUpdateError, MapToError, TranslationResult};
+
+pub use reloc::{ExportTable, load_module};
+pub use tls::{TlsBlock, TlsTemplate};
+
+pub mod reloc;
+pub mod tls;
+
+#[derive(Debug)]
+pub enum ElfLoadError {
+    /// xmas-elf解析失败
+    Parse(&'static str),
+    /// 不是64位ELF文件
+    NotElf64,
+    /// 不是小端序
+    NotLittleEndian,
+    /// 目标架构不是x86_64
+    WrongMachine,
+    /// 既不是可执行文件也不是共享目标文件
+    UnsupportedType,
+    /// 段的文件内容超出了ELF文件的范围，或文件大小大于内存大小
+    InvalidSegment(u64),
+    /// 段的地址加上偏移量后溢出或不是Canonical地址
+    AddressOverflow(u64),
+    /// 模块的加载地址没有按4KB对齐
+    NotAligned(VirtAddr),
+    /// 物理帧分配错误
+    FrameAllocateFailed,
+    /// 段所在的虚拟地址已被大页面映射
+    HugePageConflict(VirtAddr),
+    /// 导出表中没有该符号
+    UndefinedSymbol(String),
+    /// 不支持的重定位类型
+    UnsupportedRelocation(u32),
+    /// 重定位结果超出了32位的范围
+    RelocationOverflow(VirtAddr),
+    /// 节或符号索引超出范围
+    InvalidSection(u32),
+    /// 共享目标文件没有节头，无法找到需要处理的重定位
+    MissingSectionHeaders,
+    /// 写入段内容时虚拟地址没有映射到有效的物理帧
+    SegmentNotMapped(VirtAddr),
+    /// 合并共享页面的属性失败
+    FlagUpdateFailed(VirtAddr, FlagUpdateError),
+}
+
+/// 已加载的段
+#[derive(Debug, Copy, Clone)]
+pub struct LoadedSegment {
+    /// 段的起始虚拟地址（包含加载偏移量）
+    pub start: VirtAddr,
+    /// 段的结束虚拟地址（不包含）
+    pub end: VirtAddr,
+    /// 映射段使用的页面属性
+    pub flags: PageTableFlags,
+}
+
+/// 加载结果
+#[derive(Debug)]
+pub struct LoadedElf {
+    /// 入口地址（包含加载偏移量）
+    pub entry: VirtAddr,
+    /// 加载偏移量，可执行文件通常为0
+    pub bias: u64,
+    /// 所有已加载的段
+    pub segments: Vec<LoadedSegment>,
+}
+
+/// 校验ELF文件是否为x86_64的64位小端序可执行文件或共享目标文件
+pub fn validate(elf: &ElfFile) -> Result<(), ElfLoadError> {
+    match check_header(elf)? {
+        header::Type::Executable | header::Type::SharedObject => Ok(()),
+        _ => Err(ElfLoadError::UnsupportedType),
+    }
+}
+
+/// 校验ELF文件是否为x86_64的64位小端序文件，返回文件类型
+fn check_header(elf: &ElfFile) -> Result<header::Type, ElfLoadError> {
+    header::sanity_check(elf).map_err(ElfLoadError::Parse)?;
+    if elf.header.pt1.class() != Class::SixtyFour {
+        return Err(ElfLoadError::NotElf64);
+    }
+    if elf.header.pt1.data() != Data::LittleEndian {
+        return Err(ElfLoadError::NotLittleEndian);
+    }
+    if elf.header.pt2.machine().as_machine() != Machine::X86_64 {
+        return Err(ElfLoadError::WrongMachine);
+    }
+    Ok(elf.header.pt2.type_().as_type())
+}
+
+/// 根据段的权限计算页面属性，只有可写的段设置`WRITABLE`
+/// 不可执行的段只在EFER.NXE开启时设置`NO_EXECUTE`，否则第63位是保留位，设置后访问页面会触发页错误
+pub fn segment_flags(ph: &ProgramHeader) -> PageTableFlags {
+    access_flags(ph.flags().is_write(), ph.flags().is_execute())
+}
+
+/// 根据读写与执行权限计算页面属性
+fn access_flags(writable: bool, executable: bool) -> PageTableFlags {
+    let mut flags = PageTableFlags::PRESENT;
+    if writable {
+        flags |= PageTableFlags::WRITABLE;
+    }
+    if !executable && Efer::new().is_no_execute_enabled() {
+        flags |= PageTableFlags::NO_EXECUTE;
+    }
+    flags
+}
+
+/// 加载ELF文件，所有段的虚拟地址加上`bias`后映射到`mapper`中
+/// 段的页面属性为`segment_flags`与`extra_flags`（例如`USER_ACCESSIBLE`或`GLOBAL`）的并集，
+/// 内存大小超过文件大小的部分（`.bss`）被清零。两个段共享同一页面时合并页面属性
+///
+/// # Safety
+/// `phys`必须是有效的物理内存直接映射，段所在的虚拟地址范围不能与其他映射冲突
+pub unsafe fn load<M, A>(data: &[u8], mapper: &mut M, allocator: &mut A, phys: &PhysOffset, bias: u64, extra_flags: PageTableFlags)
+                         -> Result<LoadedElf, ElfLoadError>
+    where M: MapAllSize, A: FrameAllocator<Page4KB> {
+    let elf = ElfFile::new(data).map_err(ElfLoadError::Parse)?;
+    validate(&elf)?;
+
+    let mut segments = Vec::new();
+    let mut batch = MapperFlushBatch::new();
+    for ph in elf.program_iter() {
+        if ph.get_type().map_err(ElfLoadError::Parse)? != Type::Load || ph.mem_size() == 0 {
+            continue;
+        }
+        let segment = load_segment(data, &ph, mapper, allocator, phys, bias, extra_flags, &mut batch)?;
+        segments.push(segment);
+    }
+    batch.flush();
+
+    let entry = elf.header.pt2.entry_point();
+    let entry = to_virt(entry, bias)?;
+    Ok(LoadedElf { entry, bias, segments })
+}
+
+unsafe fn load_segment<M, A>(data: &[u8], ph: &ProgramHeader, mapper: &mut M, allocator: &mut A, phys: &PhysOffset,
+                             bias: u64, extra_flags: PageTableFlags, batch: &mut MapperFlushBatch)
+                             -> Result<LoadedSegment, ElfLoadError>
+    where M: MapAllSize, A: FrameAllocator<Page4KB> {
+    let (offset, file_size, mem_size) = (ph.offset(), ph.file_size(), ph.mem_size());
+    let file_end = offset.checked_add(file_size).ok_or(ElfLoadError::InvalidSegment(offset))?;
+    if file_size > mem_size || file_end > data.len() as u64 {
+        return Err(ElfLoadError::InvalidSegment(offset));
+    }
+    let start = to_virt(ph.virtual_addr(), bias)?;
+    let end = to_virt(start.as_u64().checked_add(mem_size).ok_or(ElfLoadError::AddressOverflow(start.as_u64()))?, 0)?;
+    let flags = segment_flags(ph) | extra_flags;
+
+    let mapped = map_range(mapper, allocator, phys, start, end, flags, batch)?;
+
+    // 复制文件内容，`.bss`部分显式清零以覆盖共享页面中的旧内容
+    let content = &data[offset as usize..file_end as usize];
+    let written = write_bytes(mapper, phys, start.as_u64(), content.len() as u64, |dst, done, len| {
+        ptr::copy_nonoverlapping(content.as_ptr().add(done as usize), dst, len as usize);
+    }).and_then(|_| write_bytes(mapper, phys, start.as_u64() + file_size, mem_size - file_size, |dst, _, len| {
+        ptr::write_bytes(dst, 0, len as usize);
+    }));
+    if let Err(err) = written {
+        release_pages(mapper, allocator, &mapped);
+        return Err(err);
+    }
+
+    Ok(LoadedSegment { start, end, flags })
+}
+
+/// 映射`[start, end)`覆盖的所有页面，返回新映射的页面
+/// 失败时已新映射的页面被取消映射，物理帧交还给`allocator`，与其他段共享的页面保持映射
+unsafe fn map_range<M, A>(mapper: &mut M, allocator: &mut A, phys: &PhysOffset, start: VirtAddr, end: VirtAddr,
+                          flags: PageTableFlags, batch: &mut MapperFlushBatch) -> Result<Vec<Page<Page4KB>>, ElfLoadError>
+    where M: MapAllSize, A: FrameAllocator<Page4KB> {
+    let first = align_down(start.as_u64(), Page4KB::P_SIZE);
+    let last = align_up(end.as_u64(), Page4KB::P_SIZE);
+    let mut mapped = Vec::new();
+    for addr in (first..last).step_by(Page4KB::P_SIZE as usize) {
+        let page = Page::<Page4KB>::include_address(VirtAddr::new(addr));
+        match map_segment_page(mapper, allocator, phys, page, flags, batch) {
+            Ok(true) => mapped.push(page),
+            Ok(false) => {}
+            Err(err) => {
+                release_pages(mapper, allocator, &mapped);
+                return Err(err);
+            }
+        }
+    }
+    Ok(mapped)
+}
+
+/// 取消映射`map_range`新映射的页面并将物理帧交还给`allocator`，新建的中间页表不会被释放
+/// 这些页面只通过直接映射写入过，因此不需要刷新TLB
+unsafe fn release_pages<M, A>(mapper: &mut M, allocator: &mut A, pages: &[Page<Page4KB>])
+    where M: MapAllSize, A: FrameAllocator<Page4KB> {
+    for &page in pages {
+        if let Ok((frame, flush)) = <M as Mapper<Page4KB>>::unmap(mapper, page) {
+            flush.ignore();
+            allocator.dealloc(UnusedFrame::new(frame));
+        }
+    }
+}
+
+/// 为段映射一个页面，页面已被其他段映射时合并页面属性，返回是否新映射了该页面
+unsafe fn map_segment_page<M, A>(mapper: &mut M, allocator: &mut A, phys: &PhysOffset, page: Page<Page4KB>,
+                                 flags: PageTableFlags, batch: &mut MapperFlushBatch) -> Result<bool, ElfLoadError>
+    where M: MapAllSize, A: FrameAllocator<Page4KB> {
+    match mapper.translate(page.start_address()) {
+        TranslationResult::Frame4KB { flags: old, .. } => {
+            // 任意一个段可写则可写，两个段都不可执行时才设置`NO_EXECUTE`
+            let mut merged = old | flags;
+            merged.set(PageTableFlags::NO_EXECUTE, old.contains(PageTableFlags::NO_EXECUTE) && flags.contains(PageTableFlags::NO_EXECUTE));
+            if merged != old {
+                let flush = <M as Mapper<Page4KB>>::update_flags(mapper, page, merged)
+                    .map_err(|err| ElfLoadError::FlagUpdateFailed(page.start_address(), err))?;
+                batch.push(flush);
+            }
+            return Ok(false);
+        }
+        TranslationResult::Frame2MB { .. } | TranslationResult::Frame1GB { .. } => {
+            return Err(ElfLoadError::HugePageConflict(page.start_address()));
+        }
+        TranslationResult::PageNotMapped | TranslationResult::InvalidFrameAddress(_) => {}
+    }
+
+    let frame = allocator.alloc().ok_or(ElfLoadError::FrameAllocateFailed)?;
+    ptr::write_bytes(phys.phys_to_virt(frame.start_address()).as_mut_ptr::<u8>(), 0, Page4KB::P_SIZE as usize);
+    match <M as Mapper<Page4KB>>::map_to(mapper, page, frame.frame(), flags, allocator) {
+        // 新建立的映射之前不存在，不需要刷新TLB
+        Ok(flush) => {
+            flush.ignore();
+            Ok(true)
+        }
+        Err(err) => {
+            allocator.dealloc(frame);
+            match err {
+                MapToError::FrameAllocateFailed => Err(ElfLoadError::FrameAllocateFailed),
+                MapToError::ParentEntryHugePage | MapToError::PageAlreadyMapped(_) => {
+                    Err(ElfLoadError::HugePageConflict(page.start_address()))
+                }
+            }
+        }
+    }
+}
+
+/// 通过直接映射按页写入`[addr, addr + len)`，`f`的参数为目标指针、已写入的字节数和本次写入的字节数
+/// 页面没有映射到有效的物理帧时（例如页表项中的物理地址无效）返回`SegmentNotMapped`
+unsafe fn write_bytes<M, F>(mapper: &M, phys: &PhysOffset, addr: u64, len: u64, mut f: F) -> Result<(), ElfLoadError>
+    where M: MapAllSize, F: FnMut(*mut u8, u64, u64) {
+    let mut done = 0;
+    while done < len {
+        let current = addr + done;
+        let chunk = (Page4KB::P_SIZE - current % Page4KB::P_SIZE).min(len - done);
+        let virt = VirtAddr::new(current);
+        let target = mapper.translate_addr(virt).ok_or(ElfLoadError::SegmentNotMapped(virt))?;
+        let dst = phys.phys_to_virt(target);
+        f(dst.as_mut_ptr::<u8>(), done, chunk);
+        done += chunk;
+    }
+    Ok(())
+}
+
+/// 计算加上偏移量后的虚拟地址
+fn to_virt(addr: u64, bias: u64) -> Result<VirtAddr, ElfLoadError> {
+    addr.checked_add(bias)
+        .and_then(|addr| VirtAddr::try_new(addr).ok())
+        .ok_or(ElfLoadError::AddressOverflow(addr))
+}
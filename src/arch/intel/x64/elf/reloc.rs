@@ -0,0 +1,327 @@
+///! 可重定位模块加载
+///! 支持位置无关的共享目标文件（ET_DYN）与可重定位目标文件（ET_REL），
+///! 模块中未定义的符号通过调用者提供的导出表解析，因此驱动可以与内核映像分开编译
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::ptr;
+
+use xmas_elf::ElfFile;
+use xmas_elf::header;
+use xmas_elf::program::Type;
+use xmas_elf::sections::{self, SectionData, SectionHeader, ShType};
+use xmas_elf::symbol_table::Entry;
+
+use crate::arch::intel::x64::address::{align_down, align_up, VirtAddr, VirtualAddress};
+use crate::arch::intel::x64::elf::{access_flags, check_header, load, map_range, to_virt, write_bytes};
+use crate::arch::intel::x64::elf::{ElfLoadError, LoadedElf, LoadedSegment};
+use crate::arch::intel::x64::paging::{FrameAllocator, Page4KB, PageSize};
+use crate::arch::intel::x64::paging::flags::PageTableFlags;
+use crate::arch::intel::x64::paging::mapper::{MapAllSize, MapperFlushBatch, PhysOffset};
+
+/// 无重定位
+pub const R_X86_64_NONE: u32 = 0;
+/// S + A，64位
+pub const R_X86_64_64: u32 = 1;
+/// S + A - P，32位有符号
+pub const R_X86_64_PC32: u32 = 2;
+/// L + A - P，模块不使用PLT，与`R_X86_64_PC32`相同
+pub const R_X86_64_PLT32: u32 = 4;
+/// S，写入GOT表项
+pub const R_X86_64_GLOB_DAT: u32 = 6;
+/// S，写入PLT使用的GOT表项
+pub const R_X86_64_JUMP_SLOT: u32 = 7;
+/// B + A，64位
+pub const R_X86_64_RELATIVE: u32 = 8;
+
+/// 未定义符号的节索引
+const SHN_UNDEF: u16 = 0;
+/// 绝对符号的节索引
+const SHN_ABS: u16 = 0xfff1;
+/// 公共符号（未初始化的全局变量）的节索引，符号的值是对齐要求，由加载器分配空间
+const SHN_COMMON: u16 = 0xfff2;
+
+/// 内核导出给模块使用的符号表
+#[derive(Debug, Default, Clone)]
+pub struct ExportTable {
+    symbols: BTreeMap<String, VirtAddr>,
+}
+
+impl ExportTable {
+    pub fn new() -> Self {
+        Self { symbols: BTreeMap::new() }
+    }
+
+    /// 导出一个符号，返回之前导出的同名符号地址
+    pub fn insert(&mut self, name: &str, addr: VirtAddr) -> Option<VirtAddr> {
+        self.symbols.insert(name.to_string(), addr)
+    }
+
+    /// 查找导出的符号
+    pub fn get(&self, name: &str) -> Option<VirtAddr> {
+        self.symbols.get(name).copied()
+    }
+}
+
+/// 将模块加载到以`base`开始的虚拟地址并处理重定位
+/// 1. 共享目标文件的所有段整体平移，最低的段对齐到`base`，入口地址为`e_entry`加上偏移量
+/// 2. 可重定位目标文件的所有`SHF_ALLOC`节从`base`开始按对齐依次排列，权限不同的节从新的页面开始，
+///    此时文件没有入口地址，返回的入口地址为`base`，调用者应当通过导出的符号查找初始化函数。
+///    公共符号（`SHN_COMMON`）在所有节之后分配并清零，大小为0的节不占用空间，其中的符号指向该节排列时的位置
+///
+/// `base`必须按4KB对齐，否则返回`NotAligned`
+///
+/// 模块中已定义的符号优先于导出表，未定义且不在导出表中的符号返回`UndefinedSymbol`。
+/// 共享目标文件的重定位通过节头中的`SHT_RELA`节查找，去除了节头的文件返回`MissingSectionHeaders`
+///
+/// # Safety
+/// `phys`必须是有效的物理内存直接映射，模块所在的虚拟地址范围不能与其他映射冲突
+pub unsafe fn load_module<M, A>(data: &[u8], mapper: &mut M, allocator: &mut A, phys: &PhysOffset, base: VirtAddr,
+                                extra_flags: PageTableFlags, exports: &ExportTable) -> Result<LoadedElf, ElfLoadError>
+    where M: MapAllSize, A: FrameAllocator<Page4KB> {
+    let elf = ElfFile::new(data).map_err(ElfLoadError::Parse)?;
+    match check_header(&elf)? {
+        header::Type::SharedObject => load_shared(&elf, data, mapper, allocator, phys, base, extra_flags, exports),
+        header::Type::Relocatable => load_relocatable(&elf, data, mapper, allocator, phys, base, extra_flags, exports),
+        _ => Err(ElfLoadError::UnsupportedType),
+    }
+}
+
+unsafe fn load_shared<M, A>(elf: &ElfFile, data: &[u8], mapper: &mut M, allocator: &mut A, phys: &PhysOffset,
+                            base: VirtAddr, extra_flags: PageTableFlags, exports: &ExportTable)
+                            -> Result<LoadedElf, ElfLoadError>
+    where M: MapAllSize, A: FrameAllocator<Page4KB> {
+    if !base.is_aligned(Page4KB::P_SIZE) {
+        return Err(ElfLoadError::NotAligned(base));
+    }
+    // 重定位通过节头查找，没有节头（第0项之外）的文件加载后重定位不会被处理，因此直接拒绝
+    if elf.header.pt2.sh_count() <= 1 {
+        return Err(ElfLoadError::MissingSectionHeaders);
+    }
+    let mut lowest = None;
+    for ph in elf.program_iter() {
+        if ph.get_type().map_err(ElfLoadError::Parse)? == Type::Load {
+            let addr = align_down(ph.virtual_addr(), Page4KB::P_SIZE);
+            lowest = Some(lowest.map_or(addr, |lowest: u64| lowest.min(addr)));
+        }
+    }
+    let lowest = lowest.unwrap_or(0);
+    let bias = base.as_u64().wrapping_sub(lowest);
+
+    let loaded = load(data, mapper, allocator, phys, bias, extra_flags)?;
+    for section in elf.section_iter() {
+        if section.get_type().map_err(ElfLoadError::Parse)? != ShType::Rela {
+            continue;
+        }
+        // 动态重定位的偏移量是虚拟地址，已定义符号的值同样需要加上偏移量
+        apply_rela(elf, &section, mapper, phys, exports, &BTreeMap::new(),
+                   |offset| to_virt(offset, bias),
+                   |_, value| Ok(value.wrapping_add(bias)))?;
+    }
+    Ok(loaded)
+}
+
+unsafe fn load_relocatable<M, A>(elf: &ElfFile, data: &[u8], mapper: &mut M, allocator: &mut A, phys: &PhysOffset,
+                                 base: VirtAddr, extra_flags: PageTableFlags, exports: &ExportTable)
+                                 -> Result<LoadedElf, ElfLoadError>
+    where M: MapAllSize, A: FrameAllocator<Page4KB> {
+    // 每个节加载后的地址，未加载的节为None
+    let mut addresses: Vec<Option<u64>> = Vec::new();
+    let mut segments: Vec<LoadedSegment> = Vec::new();
+    let mut batch = MapperFlushBatch::new();
+    let mut cursor = base.as_u64();
+    for section in elf.section_iter() {
+        let ty = section.get_type().map_err(ElfLoadError::Parse)?;
+        if section.flags() & sections::SHF_ALLOC == 0 {
+            addresses.push(None);
+            continue;
+        }
+        if section.size() == 0 {
+            // 空节不需要映射，但其中可能定义了符号（例如节的起始标记）
+            addresses.push(Some(align_up(cursor, section.align().max(1))));
+            continue;
+        }
+        let flags = section_flags(&section) | extra_flags;
+        let start = place(mapper, allocator, phys, &mut batch, &mut segments, &mut cursor, section.size(), section.align(), flags)?;
+
+        if ty == ShType::NoBits {
+            write_bytes(mapper, phys, start.as_u64(), section.size(), |dst, _, len| {
+                ptr::write_bytes(dst, 0, len as usize);
+            })?;
+        } else {
+            let offset = section.offset();
+            let file_end = offset.checked_add(section.size()).ok_or(ElfLoadError::InvalidSegment(offset))?;
+            if file_end > data.len() as u64 {
+                return Err(ElfLoadError::InvalidSegment(offset));
+            }
+            let content = &data[offset as usize..file_end as usize];
+            write_bytes(mapper, phys, start.as_u64(), content.len() as u64, |dst, done, len| {
+                ptr::copy_nonoverlapping(content.as_ptr().add(done as usize), dst, len as usize);
+            })?;
+        }
+
+        addresses.push(Some(start.as_u64()));
+    }
+
+    // 公共符号与`.bss`一样可写、不可执行并清零
+    let mut commons = BTreeMap::new();
+    let common_flags = access_flags(true, false) | extra_flags;
+    for section in elf.section_iter() {
+        let entries = match section.get_data(elf).map_err(ElfLoadError::Parse)? {
+            SectionData::SymbolTable64(entries) => entries,
+            _ => continue,
+        };
+        for entry in entries.iter().filter(|entry| entry.shndx() == SHN_COMMON) {
+            let name = entry.get_name(elf).map_err(ElfLoadError::Parse)?;
+            if commons.contains_key(name) {
+                continue;
+            }
+            let start = place(mapper, allocator, phys, &mut batch, &mut segments, &mut cursor, entry.size().max(1), entry.value(), common_flags)?;
+            write_bytes(mapper, phys, start.as_u64(), entry.size(), |dst, _, len| {
+                ptr::write_bytes(dst, 0, len as usize);
+            })?;
+            commons.insert(name, start.as_u64());
+        }
+    }
+    batch.flush();
+
+    let section_address = |index: u32| -> Result<u64, ElfLoadError> {
+        addresses.get(index as usize).copied().flatten().ok_or(ElfLoadError::InvalidSection(index))
+    };
+    for section in elf.section_iter() {
+        if section.get_type().map_err(ElfLoadError::Parse)? != ShType::Rela {
+            continue;
+        }
+        // 只处理作用于已加载节的重定位，例如调试信息的重定位会被跳过
+        let target = match addresses.get(section.info() as usize).copied().flatten() {
+            Some(target) => target,
+            None => continue,
+        };
+        apply_rela(elf, &section, mapper, phys, exports, &commons,
+                   |offset| to_virt(target.wrapping_add(offset), 0),
+                   |shndx, value| Ok(section_address(u32::from(shndx))?.wrapping_add(value)))?;
+    }
+
+    Ok(LoadedElf { entry: base, bias: base.as_u64(), segments })
+}
+
+/// 在`cursor`之后按`align`为`size`字节分配虚拟地址并映射页面，返回起始地址
+/// 权限不同的区域不能共享页面，否则合并后的页面可能同时可写和可执行，因此从新的页面开始
+unsafe fn place<M, A>(mapper: &mut M, allocator: &mut A, phys: &PhysOffset, batch: &mut MapperFlushBatch,
+                      segments: &mut Vec<LoadedSegment>, cursor: &mut u64, size: u64, align: u64, flags: PageTableFlags)
+                      -> Result<VirtAddr, ElfLoadError>
+    where M: MapAllSize, A: FrameAllocator<Page4KB> {
+    if segments.last().map_or(false, |last| last.flags != flags) {
+        *cursor = align_up(*cursor, Page4KB::P_SIZE);
+    }
+    let start = to_virt(align_up(*cursor, align.max(1)), 0)?;
+    let end = to_virt(start.as_u64().checked_add(size).ok_or(ElfLoadError::AddressOverflow(start.as_u64()))?, 0)?;
+
+    map_range(mapper, allocator, phys, start, end, flags, batch)?;
+
+    match segments.last_mut() {
+        Some(last) if last.flags == flags => last.end = end,
+        _ => segments.push(LoadedSegment { start, end, flags }),
+    }
+    *cursor = end.as_u64();
+    Ok(start)
+}
+
+/// 根据节的属性计算页面属性，与`segment_flags`相同
+fn section_flags(section: &SectionHeader) -> PageTableFlags {
+    access_flags(section.flags() & sections::SHF_WRITE != 0, section.flags() & sections::SHF_EXECINSTR != 0)
+}
+
+/// 处理一个`SHT_RELA`节中的所有重定位
+/// `place`将重定位的偏移量转为虚拟地址，`defined`根据节索引和符号值计算已定义符号的地址，
+/// `commons`是已分配的公共符号的地址
+unsafe fn apply_rela<M, P, D>(elf: &ElfFile, section: &SectionHeader, mapper: &M, phys: &PhysOffset,
+                              exports: &ExportTable, commons: &BTreeMap<&str, u64>, place: P, defined: D)
+                              -> Result<(), ElfLoadError>
+    where M: MapAllSize, P: Fn(u64) -> Result<VirtAddr, ElfLoadError>, D: Fn(u16, u64) -> Result<u64, ElfLoadError> {
+    let relocations = match section.get_data(elf).map_err(ElfLoadError::Parse)? {
+        SectionData::Rela64(relocations) => relocations,
+        _ => return Err(ElfLoadError::Parse("relocation section is not Rela64")),
+    };
+    let symbols = section_symbols(elf, section.link())?;
+
+    for rela in relocations {
+        let ty = rela.get_type();
+        let addend = rela.get_addend();
+        let target = place(rela.get_offset())?;
+        let symbol = |index: u32| -> Result<u64, ElfLoadError> {
+            if index == 0 {
+                return Ok(0);
+            }
+            let entry = symbols.get(index as usize).ok_or(ElfLoadError::InvalidSection(index))?;
+            match entry.shndx {
+                SHN_UNDEF => exports.get(entry.name).map(|addr| addr.as_u64())
+                    .ok_or_else(|| ElfLoadError::UndefinedSymbol(entry.name.to_string())),
+                SHN_ABS => Ok(entry.value),
+                SHN_COMMON => commons.get(entry.name).copied()
+                    .ok_or_else(|| ElfLoadError::UndefinedSymbol(entry.name.to_string())),
+                shndx => defined(shndx, entry.value),
+            }
+        };
+
+        match ty {
+            R_X86_64_NONE => {}
+            R_X86_64_64 => {
+                let value = symbol(rela.get_symbol_table_index())?.wrapping_add(addend);
+                write_value(mapper, phys, target, &value.to_le_bytes())?;
+            }
+            R_X86_64_PC32 | R_X86_64_PLT32 => {
+                let value = symbol(rela.get_symbol_table_index())?.wrapping_add(addend).wrapping_sub(target.as_u64()) as i64;
+                if value < i64::from(i32::min_value()) || value > i64::from(i32::max_value()) {
+                    return Err(ElfLoadError::RelocationOverflow(target));
+                }
+                write_value(mapper, phys, target, &(value as i32).to_le_bytes())?;
+            }
+            R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT => {
+                let value = symbol(rela.get_symbol_table_index())?;
+                write_value(mapper, phys, target, &value.to_le_bytes())?;
+            }
+            R_X86_64_RELATIVE => {
+                // 只出现在共享目标文件中，节索引0表示加载基址
+                let value = defined(0, addend)?;
+                write_value(mapper, phys, target, &value.to_le_bytes())?;
+            }
+            ty => return Err(ElfLoadError::UnsupportedRelocation(ty)),
+        }
+    }
+    Ok(())
+}
+
+/// 解析后的符号表项
+struct Symbol<'a> {
+    name: &'a str,
+    shndx: u16,
+    value: u64,
+}
+
+/// 读取索引为`index`的符号表（`.symtab`或`.dynsym`）
+fn section_symbols<'a>(elf: &ElfFile<'a>, index: u32) -> Result<Vec<Symbol<'a>>, ElfLoadError> {
+    let section = elf.section_header(index as u16).map_err(ElfLoadError::Parse)?;
+    let mut symbols = Vec::new();
+    match section.get_data(elf).map_err(ElfLoadError::Parse)? {
+        SectionData::SymbolTable64(entries) => {
+            for entry in entries {
+                symbols.push(Symbol { name: entry.get_name(elf).map_err(ElfLoadError::Parse)?, shndx: entry.shndx(), value: entry.value() });
+            }
+        }
+        SectionData::DynSymbolTable64(entries) => {
+            for entry in entries {
+                symbols.push(Symbol { name: entry.get_name(elf).map_err(ElfLoadError::Parse)?, shndx: entry.shndx(), value: entry.value() });
+            }
+        }
+        _ => return Err(ElfLoadError::InvalidSection(index)),
+    }
+    Ok(symbols)
+}
+
+/// 写入重定位结果，目标地址可能跨越页面边界
+unsafe fn write_value<M: MapAllSize>(mapper: &M, phys: &PhysOffset, target: VirtAddr, bytes: &[u8]) -> Result<(), ElfLoadError> {
+    write_bytes(mapper, phys, target.as_u64(), bytes.len() as u64, |dst, done, len| {
+        ptr::copy_nonoverlapping(bytes.as_ptr().add(done as usize), dst, len as usize);
+    })
+}
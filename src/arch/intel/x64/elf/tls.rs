@@ -0,0 +1,125 @@
+///! 内核线程本地存储（ELF TLS）
+///! x86_64使用TLS Variant II布局：线程指针（FS基址）指向TCB，TLS块紧挨着位于线程指针之前，
+///! TCB的第一个字保存线程指针本身，编译器通过`mov %fs:0, %rax`获取线程指针。
+///! 每个线程或CPU分配一个`TlsBlock`，复制`.tdata`并清零`.tbss`后加载到FS基址，`#[thread_local]`静态变量即可使用
+///!
+///! ```text
+///!   block start                          thread pointer
+///!   |<-- align_up(mem_size, align) -->|
+///!   +---------+---------+-------------+----------------+
+///!   |  .tdata |  .tbss  |   padding   |  TCB(self ptr) |
+///!   +---------+---------+-------------+----------------+
+///! ```
+///!
+///! 链接器按照`-align_up(mem_size, align)`计算TLS变量相对线程指针的偏移量，因此`.tdata`从块的起始位置开始，
+///! 对齐产生的填充位于`.tbss`与TCB之间
+use alloc::alloc::{alloc_zeroed, dealloc};
+use core::alloc::Layout;
+use core::mem::size_of;
+use core::ptr::{self, NonNull};
+
+use xmas_elf::ElfFile;
+use xmas_elf::program::Type;
+
+use crate::arch::intel::chips::msr_set::FsBase;
+use crate::arch::intel::x64::address::{align_up, VirtAddr, VirtualAddress};
+use crate::arch::intel::x64::elf::{check_header, ElfLoadError};
+
+/// TCB的大小，目前只包含指向自身的指针
+pub const TCB_SIZE: usize = size_of::<usize>();
+
+/// `PT_TLS`段描述的TLS模板
+#[derive(Debug, Copy, Clone)]
+pub struct TlsTemplate<'a> {
+    /// `.tdata`的初始内容
+    tdata: &'a [u8],
+    /// `.tdata`和`.tbss`的总大小
+    mem_size: usize,
+    /// TLS块的对齐
+    align: usize,
+}
+
+impl<'a> TlsTemplate<'a> {
+    /// 使用`.tdata`的初始内容、TLS段的内存大小和对齐创建模板，对齐为0时视为1
+    pub fn new(tdata: &'a [u8], mem_size: usize, align: usize) -> Result<Self, ElfLoadError> {
+        let align = align.max(1);
+        if tdata.len() > mem_size || !align.is_power_of_two() {
+            return Err(ElfLoadError::InvalidSegment(mem_size as u64));
+        }
+        Ok(Self { tdata, mem_size, align })
+    }
+
+    /// 从ELF文件中读取`PT_TLS`段，没有TLS段时返回None
+    pub fn from_elf(data: &'a [u8]) -> Result<Option<Self>, ElfLoadError> {
+        let elf = ElfFile::new(data).map_err(ElfLoadError::Parse)?;
+        check_header(&elf)?;
+        for ph in elf.program_iter() {
+            if ph.get_type().map_err(ElfLoadError::Parse)? != Type::Tls {
+                continue;
+            }
+            let (offset, file_size) = (ph.offset(), ph.file_size());
+            let file_end = offset.checked_add(file_size).ok_or(ElfLoadError::InvalidSegment(offset))?;
+            if file_end > data.len() as u64 {
+                return Err(ElfLoadError::InvalidSegment(offset));
+            }
+            let tdata = &data[offset as usize..file_end as usize];
+            return Self::new(tdata, ph.mem_size() as usize, ph.align() as usize).map(Some);
+        }
+        Ok(None)
+    }
+
+    /// TLS数据占用的大小，即线程指针到TLS块起始位置的距离
+    pub fn tls_size(&self) -> usize {
+        align_up(self.mem_size as u64, self.align as u64) as usize
+    }
+
+    /// 包含TCB的整个块的内存布局
+    pub fn block_layout(&self) -> Layout {
+        let align = self.align.max(TCB_SIZE);
+        Layout::from_size_align(self.tls_size() + TCB_SIZE, align).expect("invalid tls layout")
+    }
+}
+
+/// 一个线程的TLS块
+#[derive(Debug)]
+pub struct TlsBlock {
+    block: NonNull<u8>,
+    layout: Layout,
+    thread_pointer: VirtAddr,
+}
+
+impl TlsBlock {
+    /// 从堆中分配TLS块，复制`.tdata`，清零`.tbss`并设置TCB自指针，分配失败时返回None
+    pub fn new(template: &TlsTemplate) -> Option<Self> {
+        let layout = template.block_layout();
+        let block = NonNull::new(unsafe { alloc_zeroed(layout) })?;
+        let tls_size = template.tls_size();
+        unsafe {
+            // `.tdata`位于线程指针之前`tls_size`字节处，即块的起始位置，`.tbss`已经由`alloc_zeroed`清零
+            ptr::copy_nonoverlapping(template.tdata.as_ptr(), block.as_ptr(), template.tdata.len());
+            let tcb = block.as_ptr().add(tls_size) as *mut usize;
+            tcb.write(tcb as usize);
+        }
+        let thread_pointer = VirtAddr::new(block.as_ptr() as u64 + tls_size as u64);
+        Some(Self { block, layout, thread_pointer })
+    }
+
+    /// 线程指针，即TCB的地址
+    pub fn thread_pointer(&self) -> VirtAddr {
+        self.thread_pointer
+    }
+
+    /// 将线程指针加载到FS基址
+    ///
+    /// # Safety
+    /// 在当前线程或CPU切换到其他TLS块之前，该块不能被释放
+    pub unsafe fn activate(&self) {
+        FsBase::new().write_raw(self.thread_pointer.as_u64());
+    }
+}
+
+impl Drop for TlsBlock {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.block.as_ptr(), self.layout) }
+    }
+}
@@ -0,0 +1,252 @@
+///! 可选的multiboot2内核入口（需要启用`entry`特性）
+///! 提供multiboot2头、32位启动代码以及进入64位模式后对内核入口函数的调用，使用方法如下
+///!
+///! ```ignore
+///! libarch::multiboot2_header!();
+///! libarch::entry_point!(kmain);
+///!
+///! fn kmain(boot_info: libarch::arch::intel::x64::entry::BootInformation) -> ! {
+///!     // 初始化堆之后可以使用`BootInfo::from_multiboot2(boot_info.start_address())`提取启动数据
+///!     loop {}
+///! }
+///! ```
+///!
+///! 链接脚本需要将`.multiboot_header`放在内核映像的前32KB中，并将`.text.boot`、`.rodata.boot`和`.bss.boot`
+///! 链接到4GB以下物理地址与虚拟地址相同的位置，内核需要使用`relocation-model=static`编译。
+///! 启动代码执行以下操作：
+///! 1. 检查引导程序传入的魔数和CPU是否支持长模式，失败时在VGA文本缓冲区打印`ERR`后停机
+///! 2. 使用2MB页面恒等映射前1GB物理内存，并将最后一个P4项指向P4自身，之后可以直接使用`RecursivePageTable`
+///! 3. 开启PAE、`EFER.LME`、分页和写保护，CPU支持NX时同时开启`EFER.NXE`
+///! 4. 加载只包含64位代码段的临时GDT，跳转到64位代码，将数据段寄存器清零后调用`entry_point!`指定的函数
+use core::mem::size_of;
+
+pub use multiboot2::BootInformation;
+
+/// multiboot2头的魔数
+pub const MULTIBOOT2_HEADER_MAGIC: u32 = 0xE852_50D6;
+/// 引导程序通过eax传入的魔数
+pub const MULTIBOOT2_BOOTLOADER_MAGIC: u32 = 0x36D7_6289;
+/// i386保护模式
+const ARCHITECTURE_I386: u32 = 0;
+
+/// multiboot2头，`tags`必须以`EndTag`结束
+#[repr(C, align(8))]
+pub struct Multiboot2Header<T> {
+    magic: u32,
+    architecture: u32,
+    header_length: u32,
+    checksum: u32,
+    tags: T,
+}
+
+impl<T> Multiboot2Header<T> {
+    pub const fn new(tags: T) -> Self {
+        let header_length = size_of::<Self>() as u32;
+        Self {
+            magic: MULTIBOOT2_HEADER_MAGIC,
+            architecture: ARCHITECTURE_I386,
+            header_length,
+            checksum: 0_u32.wrapping_sub(MULTIBOOT2_HEADER_MAGIC).wrapping_sub(ARCHITECTURE_I386).wrapping_sub(header_length),
+            tags,
+        }
+    }
+}
+
+/// 结束标签
+#[repr(C, align(8))]
+pub struct EndTag {
+    ty: u16,
+    flags: u16,
+    size: u32,
+}
+
+impl EndTag {
+    pub const fn new() -> Self {
+        Self { ty: 0, flags: 0, size: 8 }
+    }
+}
+
+/// 帧缓冲区请求标签，宽高和颜色深度为0表示没有要求
+#[repr(C, align(8))]
+pub struct FramebufferTag {
+    ty: u16,
+    flags: u16,
+    size: u32,
+    width: u32,
+    height: u32,
+    depth: u32,
+}
+
+impl FramebufferTag {
+    /// 创建可选的帧缓冲区请求，引导程序无法满足时依旧可以启动
+    pub const fn new(width: u32, height: u32, depth: u32) -> Self {
+        Self { ty: 5, flags: 1, size: 20, width, height, depth }
+    }
+}
+
+/// 带有帧缓冲区请求的标签列表
+#[repr(C)]
+pub struct FramebufferTags {
+    pub framebuffer: FramebufferTag,
+    pub end: EndTag,
+}
+
+/// 根据引导程序传入的启动信息地址创建`BootInformation`，只在`entry_point!`中使用
+///
+/// # Safety
+/// `addr`必须是引导程序通过ebx传入的地址
+#[doc(hidden)]
+pub unsafe fn boot_information(addr: usize) -> BootInformation {
+    multiboot2::load(addr)
+}
+
+/// 在`.multiboot_header`节中生成multiboot2头
+/// `multiboot2_header!()`只包含结束标签，`multiboot2_header!(framebuffer: 宽, 高, 颜色深度)`额外请求帧缓冲区
+#[macro_export]
+macro_rules! multiboot2_header {
+    () => {
+        #[used]
+        #[link_section = ".multiboot_header"]
+        static __LIBARCH_MULTIBOOT2_HEADER: $crate::arch::intel::x64::entry::Multiboot2Header<$crate::arch::intel::x64::entry::EndTag> =
+            $crate::arch::intel::x64::entry::Multiboot2Header::new($crate::arch::intel::x64::entry::EndTag::new());
+    };
+    (framebuffer: $width:expr, $height:expr, $depth:expr) => {
+        #[used]
+        #[link_section = ".multiboot_header"]
+        static __LIBARCH_MULTIBOOT2_HEADER: $crate::arch::intel::x64::entry::Multiboot2Header<$crate::arch::intel::x64::entry::FramebufferTags> =
+            $crate::arch::intel::x64::entry::Multiboot2Header::new($crate::arch::intel::x64::entry::FramebufferTags {
+                framebuffer: $crate::arch::intel::x64::entry::FramebufferTag::new($width, $height, $depth),
+                end: $crate::arch::intel::x64::entry::EndTag::new(),
+            });
+    };
+}
+
+/// 指定内核入口函数，函数签名必须为`fn(BootInformation) -> !`
+/// 调用时只有启动栈和恒等映射可用，堆还没有初始化
+#[macro_export]
+macro_rules! entry_point {
+    ($kmain:path) => {
+        #[no_mangle]
+        pub unsafe extern "C" fn __libarch_kmain(multiboot_addr: usize) -> ! {
+            let kmain: fn($crate::arch::intel::x64::entry::BootInformation) -> ! = $kmain;
+            kmain($crate::arch::intel::x64::entry::boot_information(multiboot_addr))
+        }
+    };
+}
+
+global_asm!(r#"
+.section .text.boot, "ax"
+.global _start
+.code32
+_start:
+    movl $__libarch_boot_stack_top, %esp
+    movl %ebx, %edi
+    cmpl $0x36d76289, %eax
+    jne 2f
+
+    # 检查是否支持长模式
+    movl $0x80000000, %eax
+    cpuid
+    cmpl $0x80000001, %eax
+    jb 2f
+    movl $0x80000001, %eax
+    cpuid
+    testl $(1 << 29), %edx
+    jz 2f
+    # 保存扩展功能位，第20位表示是否支持NX
+    movl %edx, %esi
+
+    # P4[0] -> P3, P4[511] -> P4, P3[0] -> P2
+    movl $__libarch_boot_p3, %eax
+    orl $0x3, %eax
+    movl %eax, __libarch_boot_p4
+    movl $__libarch_boot_p4, %eax
+    orl $0x3, %eax
+    movl %eax, __libarch_boot_p4 + 511 * 8
+    movl $__libarch_boot_p2, %eax
+    orl $0x3, %eax
+    movl %eax, __libarch_boot_p3
+
+    # 使用2MB页面恒等映射前1GB
+    xorl %ecx, %ecx
+1:
+    movl %ecx, %eax
+    shll $21, %eax
+    orl $0x83, %eax
+    movl %eax, __libarch_boot_p2(, %ecx, 8)
+    incl %ecx
+    cmpl $512, %ecx
+    jne 1b
+
+    # CR4.PAE
+    movl %cr4, %eax
+    orl $(1 << 5), %eax
+    movl %eax, %cr4
+    movl $__libarch_boot_p4, %eax
+    movl %eax, %cr3
+
+    # EFER.LME，CPU支持NX时同时开启EFER.NXE，否则第11位是保留位，写入会引发#GP
+    movl $0xC0000080, %ecx
+    rdmsr
+    orl $(1 << 8), %eax
+    testl $(1 << 20), %esi
+    jz 5f
+    orl $(1 << 11), %eax
+5:
+    wrmsr
+
+    # CR0.PG | CR0.WP
+    movl %cr0, %eax
+    orl $((1 << 31) | (1 << 16)), %eax
+    movl %eax, %cr0
+
+    lgdt __libarch_boot_gdt_pointer
+    ljmp $0x08, $__libarch_long_mode_start
+
+2:
+    movl $0x4f524f45, 0xb8000
+    movl $0x4f204f52, 0xb8004
+3:
+    hlt
+    jmp 3b
+
+.code64
+__libarch_long_mode_start:
+    xorw %ax, %ax
+    movw %ax, %ss
+    movw %ax, %ds
+    movw %ax, %es
+    movw %ax, %fs
+    movw %ax, %gs
+    # 32位模式下写入的寄存器高32位未定义
+    movl %edi, %edi
+    xorq %rbp, %rbp
+    call __libarch_kmain
+4:
+    hlt
+    jmp 4b
+
+.section .rodata.boot, "a"
+.align 8
+__libarch_boot_gdt:
+    .quad 0
+    # 64位代码段：可执行、代码/数据段、存在、长模式
+    .quad (1 << 43) | (1 << 44) | (1 << 47) | (1 << 53)
+__libarch_boot_gdt_pointer:
+    .word __libarch_boot_gdt_pointer - __libarch_boot_gdt - 1
+    .quad __libarch_boot_gdt
+
+.section .bss.boot, "aw", @nobits
+.align 4096
+__libarch_boot_p4:
+    .skip 4096
+__libarch_boot_p3:
+    .skip 4096
+__libarch_boot_p2:
+    .skip 4096
+__libarch_boot_stack_bottom:
+    .skip 16 * 1024
+__libarch_boot_stack_top:
+
+.section .text
+"#);
@@ -0,0 +1,102 @@
+///! 从BIOS E820内存布局中导入内存布局
+///! E820项由实模式下的`int 0x15, eax=0xE820`返回，ACPI 3.0之后每项增加了4字节的扩展属性
+use bitflags::bitflags;
+
+use crate::arch::intel::x64::memory::{MemorySpace, MemoryType};
+
+bitflags! {
+    /// ACPI 3.0定义的E820扩展属性
+    pub struct E820Attribute: u32 {
+        /// 如果为0，该项应当被忽略
+        const ENABLED =         1 << 0;
+        /// 非易失性内存，内容在断电和休眠后保留，不能作为普通内存使用
+        const NON_VOLATILE =    1 << 1;
+        /// 访问速度较慢的内存
+        const SLOW_ACCESS =     1 << 2;
+        /// 用于记录硬件错误日志的内存
+        const ERROR_LOG =       1 << 3;
+    }
+}
+
+/// E820内存布局项
+/// BIOS只返回20字节时，扩展属性应当设置为`E820Attribute::ENABLED`
+#[derive(Debug, Copy, Clone)]
+#[repr(C, packed)]
+pub struct E820Entry {
+    pub base: u64,
+    pub length: u64,
+    pub ty: u32,
+    pub ext_attr: u32,
+}
+
+impl E820Entry {
+    /// 可用内存
+    pub const USABLE: u32 = 1;
+    /// 保留内存
+    pub const RESERVED: u32 = 2;
+    /// ACPI表使用的内存，读取ACPI表后可以回收
+    pub const ACPI_RECLAIMABLE: u32 = 3;
+    /// ACPI NVS内存，休眠时需要保存和恢复
+    pub const ACPI_NVS: u32 = 4;
+    /// 损坏的内存
+    pub const UNUSABLE: u32 = 5;
+    /// 持久性内存（NVDIMM），ACPI 6.0定义
+    pub const PERSISTENT: u32 = 7;
+
+    /// 创建不带扩展属性的E820项
+    pub fn new(base: u64, length: u64, ty: u32) -> Self {
+        Self {
+            base,
+            length,
+            ty,
+            ext_attr: E820Attribute::ENABLED.bits(),
+        }
+    }
+
+    /// 扩展属性
+    pub fn attribute(&self) -> E820Attribute {
+        E820Attribute::from_bits_truncate(self.ext_attr)
+    }
+
+    /// 转为`MemoryType`，没有设置`ENABLED`的项返回None
+    /// 记录错误日志的内存标记为`ErrorArea`，
+    /// 持久性内存和设置了`NON_VOLATILE`的可用内存保存的是需要在重启后保留的数据，与ACPI NVS或休眠镜像无关，
+    /// 因此标记为普通的保留内存，交给持久性内存驱动管理
+    pub fn area_type(&self) -> Option<MemoryType> {
+        let attr = self.attribute();
+        if !attr.contains(E820Attribute::ENABLED) {
+            return None;
+        }
+        if attr.contains(E820Attribute::ERROR_LOG) {
+            return Some(MemoryType::ErrorArea);
+        }
+        let ty = match self.ty {
+            Self::USABLE if attr.contains(E820Attribute::NON_VOLATILE) => MemoryType::ReservedArea,
+            Self::USABLE => MemoryType::FreeArea,
+            Self::ACPI_RECLAIMABLE => MemoryType::ACPIArea,
+            Self::ACPI_NVS => MemoryType::ACPIReservedArea,
+            Self::UNUSABLE => MemoryType::Defective,
+            Self::PERSISTENT => MemoryType::ReservedArea,
+            _ => MemoryType::ReservedArea,
+        };
+        Some(ty)
+    }
+}
+
+impl MemorySpace {
+    /// 根据E820内存布局创建规范化后的内存布局，BIOS返回的重叠项按类型优先级处理
+    pub fn from_e820(entries: &[E820Entry]) -> Self {
+        let mut space = MemorySpace::new();
+        for entry in entries {
+            let (base, length) = (entry.base, entry.length);
+            if length == 0 {
+                continue;
+            }
+            if let Some(ty) = entry.area_type() {
+                space.add_area(base, base.saturating_add(length), ty, length);
+            }
+        }
+        space.normalize();
+        space
+    }
+}
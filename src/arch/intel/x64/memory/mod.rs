@@ -0,0 +1,266 @@
+pub use registry::{hot_add_area, init_memory_map, register_listener, reserve_area, with_memory_map, MemoryMapError, MemoryMapEvent};
+
+use alloc::vec::{self, Vec};
+use core::iter::once;
+use core::ops::Range;
+
+use crate::arch::intel::x64::address::{align_down, align_up};
+
+pub mod multiboot;
+pub mod uefi;
+pub mod e820;
+pub mod registry;
+
+/// 空闲区域对齐的页面大小
+const PAGE_SIZE: u64 = 0x1000;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum MemoryType {
+    EmptyArea,
+    FreeArea,
+    UsedArea,
+    ReservedArea,
+    ACPIArea,
+    ACPIReservedArea,
+    ReservedHibernate,
+    Defective,
+    UefiRunTimeCode,
+    UefiRunTimeData,
+    MMIO,
+    MMIOPortArea,
+    ErrorArea,
+}
+
+impl MemoryType {
+    /// 区域重叠时的优先级，优先级高的类型覆盖优先级低的类型
+    /// `EmptyArea`的优先级最低，规范化时会被丢弃。
+    /// ACPI NVS、休眠保留区域和损坏的内存即使被保留为已使用也不能在之后被释放，因此优先级高于`UsedArea`
+    pub fn precedence(&self) -> u8 {
+        match self {
+            MemoryType::EmptyArea => 0,
+            MemoryType::FreeArea => 1,
+            MemoryType::ACPIArea => 2,
+            MemoryType::UefiRunTimeCode | MemoryType::UefiRunTimeData => 3,
+            MemoryType::MMIO | MemoryType::MMIOPortArea => 4,
+            MemoryType::UsedArea | MemoryType::ReservedArea => 5,
+            MemoryType::ACPIReservedArea | MemoryType::ReservedHibernate => 6,
+            MemoryType::ErrorArea => 7,
+            MemoryType::Defective => 8,
+        }
+    }
+}
+
+impl Default for MemoryType {
+    fn default() -> Self {
+        MemoryType::EmptyArea
+    }
+}
+
+/// memory map area
+#[derive(Copy, Clone, Debug, Default)]
+pub struct MemoryArea {
+    /// area start address
+    pub start_addr: u64,
+    /// area end address (exclusive)
+    pub end_addr: u64,
+    pub length: u64,
+    pub ty: MemoryType,
+}
+
+impl MemoryArea {
+    pub fn new(start_addr: u64, end_addr: u64, ty: MemoryType, len: u64) -> Self {
+        Self {
+            start_addr,
+            end_addr,
+            ty,
+            length: len,
+        }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.length
+    }
+
+    pub fn start_address(&self) -> u64 {
+        self.start_addr
+    }
+
+    /// 区域的结束地址（不包含），`length`不为0时以`length`为准，否则使用`end_addr`
+    pub fn end_address(&self) -> u64 {
+        if self.length != 0 {
+            self.start_addr.saturating_add(self.length)
+        } else {
+            self.end_addr
+        }
+    }
+
+    fn from_range(range: Range<u64>, ty: MemoryType) -> Self {
+        Self::new(range.start, range.end, ty, range.end - range.start)
+    }
+}
+
+#[derive(Debug)]
+pub struct MemorySpace {
+    pub(crate) space: Vec<MemoryArea>,
+}
+
+impl MemorySpace {
+    pub fn new() -> Self {
+        Self {
+            space: Vec::new(),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&MemoryArea> + '_ {
+        self.space.iter()
+    }
+
+    pub fn add_area(&mut self, start_addr: u64, end_addr: u64, ty: MemoryType, len: u64) {
+        self.space.push(MemoryArea::new(start_addr, end_addr, ty, len))
+    }
+
+    /// 规范化内存布局
+    /// 1. 按起始地址排序，重叠部分按`MemoryType::precedence`保留优先级高的类型
+    /// 2. 合并相邻的同类型区域
+    /// 3. 空闲区域向内按4KB对齐，对齐后为空的区域被丢弃
+    ///
+    /// 规范化后所有区域的`end_addr`与`length`保持一致
+    pub fn normalize(&mut self) {
+        let mut bounds: Vec<u64> = self.space.iter()
+            .filter(|area| area.ty != MemoryType::EmptyArea && area.end_address() > area.start_addr)
+            .flat_map(|area| once(area.start_addr).chain(once(area.end_address())))
+            .collect();
+        bounds.sort_unstable();
+        bounds.dedup();
+
+        let mut space: Vec<MemoryArea> = Vec::with_capacity(self.space.len());
+        for window in bounds.windows(2) {
+            let (start, end) = (window[0], window[1]);
+            let ty = self.space.iter()
+                .filter(|area| area.start_addr <= start && area.end_address() >= end)
+                .map(|area| area.ty)
+                .max_by_key(|ty| ty.precedence());
+            match ty {
+                Some(ty) if ty != MemoryType::EmptyArea => {
+                    match space.last_mut() {
+                        Some(last) if last.ty == ty && last.end_addr == start => {
+                            last.end_addr = end;
+                            last.length = end - last.start_addr;
+                        }
+                        _ => space.push(MemoryArea::from_range(start..end, ty)),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        self.space = space.into_iter()
+            .filter_map(|area| {
+                if area.ty != MemoryType::FreeArea {
+                    return Some(area);
+                }
+                let start = align_up(area.start_addr, PAGE_SIZE);
+                let end = align_down(area.end_addr, PAGE_SIZE);
+                if start < end {
+                    Some(MemoryArea::from_range(start..end, area.ty))
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
+
+    /// 将`range`标记为`ty`类型，与之重叠的区域会被拆分
+    /// 用于从内存布局中划出内核映像，initrd或者帧缓冲区等区域
+    ///
+    /// 重叠部分按照`MemoryType::precedence`处理，优先级高于`ty`的区域（例如损坏的内存和ACPI NVS）保持不变，
+    /// 之后释放保留的区域时不会使这些内存变为可分配
+    pub fn reserve(&mut self, range: Range<u64>, ty: MemoryType) {
+        if range.start >= range.end {
+            return;
+        }
+        let overlaps = |area: &MemoryArea| area.end_address() > range.start && area.start_addr < range.end;
+        let mut space = Vec::with_capacity(self.space.len() + 2);
+        let mut stronger = Vec::new();
+        for area in self.space.drain(..) {
+            let area_end = area.end_address();
+            if !overlaps(&area) {
+                space.push(area);
+                continue;
+            }
+            if area.ty.precedence() > ty.precedence() {
+                stronger.push(area.start_addr..area_end);
+                space.push(area);
+                continue;
+            }
+            if area.start_addr < range.start {
+                space.push(MemoryArea::from_range(area.start_addr..range.start, area.ty));
+            }
+            if area_end > range.end {
+                space.push(MemoryArea::from_range(range.end..area_end, area.ty));
+            }
+        }
+        // 只有没有被更高优先级区域覆盖的部分被标记为`ty`
+        stronger.sort_unstable_by_key(|area| area.start);
+        let mut start = range.start;
+        for area in stronger {
+            if area.start > start {
+                space.push(MemoryArea::from_range(start..area.start.min(range.end), ty));
+            }
+            start = start.max(area.end);
+        }
+        if start < range.end {
+            space.push(MemoryArea::from_range(start..range.end, ty));
+        }
+        space.sort_unstable_by_key(|area| area.start_addr);
+        self.space = space;
+    }
+
+    /// 所有空闲区域的字节数
+    pub fn total_usable(&self) -> u64 {
+        self.space.iter()
+            .filter(|area| area.ty == MemoryType::FreeArea)
+            .map(|area| area.end_address() - area.start_addr)
+            .sum()
+    }
+
+    /// 最大的空闲区域
+    pub fn largest_free(&self) -> Option<MemoryArea> {
+        self.space.iter()
+            .filter(|area| area.ty == MemoryType::FreeArea)
+            .max_by_key(|area| area.end_address() - area.start_addr)
+            .copied()
+    }
+}
+
+/// 遍历全局内存布局中指定类型的内存区域
+/// 创建时在一次读锁内复制所有区域，之后热添加或保留区域不会影响正在进行的遍历。
+///
+/// # 不兼容变更
+/// 全局内存布局可以被热添加或保留区域修改，无法再返回`&'static MemoryArea`，因此：
+/// * `Item`由`&'static MemoryArea`改为`MemoryArea`（`MemoryArea`实现了`Copy`，原来解引用的调用者去掉`*`即可）
+/// * `MEMORY_AREA`静态数组已被移除，需要访问整个内存布局时使用`with_memory_map`
+#[derive(Clone)]
+pub struct MemoryAreaIter {
+    ty: MemoryType,
+    areas: vec::IntoIter<MemoryArea>,
+}
+
+impl MemoryAreaIter {
+    pub fn new(ty: MemoryType) -> Self {
+        Self {
+            ty,
+            areas: registry::areas().into_iter(),
+        }
+    }
+}
+
+impl Iterator for MemoryAreaIter {
+    type Item = MemoryArea;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let ty = self.ty;
+        self.areas.find(|area| area.ty == ty)
+    }
+}
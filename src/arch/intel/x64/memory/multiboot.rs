@@ -0,0 +1,101 @@
+///! 从multiboot2启动信息中导入内存布局
+use core::mem::size_of;
+
+use multiboot2::BootInformation;
+
+use crate::arch::intel::x64::address::{align_down, align_up};
+use crate::arch::intel::x64::boot::{TagHeader, TagIter};
+use crate::arch::intel::x64::memory::{MemorySpace, MemoryType};
+
+/// 内存布局标签的类型
+const MEMORY_MAP_TAG: u32 = 6;
+/// 保留区域按4KB对齐，避免部分被占用的物理帧被分配
+const RESERVE_ALIGN: u64 = 0x1000;
+
+/// 内存布局标签，后面紧跟`entry_size`大小的内存布局项
+#[allow(dead_code)]
+#[repr(C)]
+struct MemoryMapTag {
+    header: TagHeader,
+    entry_size: u32,
+    entry_version: u32,
+}
+
+/// 内存布局项
+#[allow(dead_code)]
+#[repr(C)]
+struct MemoryMapEntry {
+    base_addr: u64,
+    length: u64,
+    ty: u32,
+    reserved: u32,
+}
+
+/// 将multiboot2内存布局项的类型转为`MemoryType`
+fn memory_type(ty: u32) -> MemoryType {
+    match ty {
+        1 => MemoryType::FreeArea,
+        3 => MemoryType::ACPIArea,
+        4 => MemoryType::ACPIReservedArea,
+        5 => MemoryType::Defective,
+        _ => MemoryType::ReservedArea,
+    }
+}
+
+impl MemorySpace {
+    /// 根据multiboot2启动信息创建内存布局，内核ELF段的物理地址与虚拟地址相同
+    pub fn from_multiboot2(info: &BootInformation) -> Self {
+        Self::from_multiboot2_with_offset(info, 0)
+    }
+
+    /// 根据multiboot2启动信息创建内存布局
+    /// 内存布局项会转为对应类型的内存区域，此外以下区域会从空闲区域中保留
+    /// 1. 所有需要加载的内核ELF段，段的物理地址为虚拟地址减去`kernel_offset`
+    /// 2. 所有启动模块
+    /// 3. 启动信息结构本身
+    ///
+    /// `multiboot2`的`memory_areas`只返回可用区域，因此这里直接解析内存布局标签
+    pub fn from_multiboot2_with_offset(info: &BootInformation, kernel_offset: u64) -> Self {
+        let mut space = MemorySpace::new();
+        unsafe { space.add_memory_map(info) };
+        space.normalize();
+
+        if let Some(elf) = info.elf_sections_tag() {
+            for section in elf.sections().filter(|section| section.is_allocated()) {
+                let start = section.start_address().wrapping_sub(kernel_offset);
+                let end = section.end_address().wrapping_sub(kernel_offset);
+                space.reserve_aligned(start, end);
+            }
+        }
+        for module in info.module_tags() {
+            space.reserve_aligned(u64::from(module.start_address()), u64::from(module.end_address()));
+        }
+        space.reserve_aligned(info.start_address() as u64, info.end_address() as u64);
+        space.normalize();
+        space
+    }
+
+    /// 遍历启动信息中的所有标签，将内存布局标签中的所有项加入内存布局
+    unsafe fn add_memory_map(&mut self, info: &BootInformation) {
+        for (_, current, size) in TagIter::new(info).filter(|(ty, _, _)| *ty == MEMORY_MAP_TAG) {
+            let tag = &*(current as *const MemoryMapTag);
+            let tag_end = current + size;
+            let mut entry = current + size_of::<MemoryMapTag>();
+            while tag.entry_size != 0 && entry + size_of::<MemoryMapEntry>() <= tag_end {
+                let area = &*(entry as *const MemoryMapEntry);
+                if area.length != 0 {
+                    let end_addr = area.base_addr.saturating_add(area.length);
+                    self.add_area(area.base_addr, end_addr, memory_type(area.ty), area.length);
+                }
+                entry += tag.entry_size as usize;
+            }
+        }
+    }
+
+    /// 按4KB对齐后将`[start, end)`保留为已使用区域
+    fn reserve_aligned(&mut self, start: u64, end: u64) {
+        if start < end {
+            self.reserve(align_down(start, RESERVE_ALIGN)..align_up(end, RESERVE_ALIGN), MemoryType::UsedArea);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+///! 全局内存布局
+///! 启动代码解析内存布局后初始化一次，之后可以热添加或保留区域，并通知注册的监听函数
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use lazy_static::lazy_static;
+use spin::RwLock;
+
+use crate::arch::intel::x64::memory::{MemoryArea, MemorySpace, MemoryType};
+
+lazy_static! {
+    static ref MEMORY_MAP: RwLock<MemoryMap> = RwLock::new(MemoryMap::new());
+}
+
+/// 内存布局变化事件
+#[derive(Debug, Copy, Clone)]
+pub enum MemoryMapEvent {
+    /// 内存布局已初始化
+    Initialized,
+    /// 热添加了新的区域
+    AreaAdded(MemoryArea),
+    /// 区域被保留为指定类型
+    AreaReserved(MemoryArea),
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum MemoryMapError {
+    /// 内存布局已经初始化
+    AlreadyInitialized,
+    /// 内存布局还没有初始化
+    NotInitialized,
+    /// 区域为空
+    EmptyRange,
+}
+
+/// 内存布局变化的监听函数，调用时不持有内存布局的锁
+pub type MemoryMapListener = fn(&MemoryMapEvent);
+
+struct MemoryMap {
+    space: MemorySpace,
+    initialized: bool,
+    listeners: Vec<MemoryMapListener>,
+}
+
+impl MemoryMap {
+    fn new() -> Self {
+        Self {
+            space: MemorySpace::new(),
+            initialized: false,
+            listeners: Vec::new(),
+        }
+    }
+}
+
+/// 使用启动时解析的内存布局初始化全局内存布局，只能调用一次
+/// 传入的内存布局会被规范化
+pub fn init_memory_map(mut space: MemorySpace) -> Result<(), MemoryMapError> {
+    space.normalize();
+    let listeners = {
+        let mut map = MEMORY_MAP.write();
+        if map.initialized {
+            return Err(MemoryMapError::AlreadyInitialized);
+        }
+        map.space = space;
+        map.initialized = true;
+        map.listeners.clone()
+    };
+    notify(&listeners, MemoryMapEvent::Initialized);
+    Ok(())
+}
+
+/// 全局内存布局是否已经初始化
+pub fn is_initialized() -> bool {
+    MEMORY_MAP.read().initialized
+}
+
+/// 热添加一个区域，已有区域的重叠部分按类型优先级处理
+pub fn hot_add_area(range: Range<u64>, ty: MemoryType) -> Result<(), MemoryMapError> {
+    let area = modify(range, ty, |space, range, ty| {
+        space.add_area(range.start, range.end, ty, range.end - range.start);
+        space.normalize();
+    })?;
+    notify_current(MemoryMapEvent::AreaAdded(area));
+    Ok(())
+}
+
+/// 将区域保留为指定类型，与之重叠的区域都会被拆分，优先级高于`ty`的部分保持不变
+pub fn reserve_area(range: Range<u64>, ty: MemoryType) -> Result<(), MemoryMapError> {
+    let area = modify(range, ty, |space, range, ty| {
+        space.reserve(range, ty);
+        space.normalize();
+    })?;
+    notify_current(MemoryMapEvent::AreaReserved(area));
+    Ok(())
+}
+
+/// 注册内存布局变化的监听函数
+pub fn register_listener(listener: MemoryMapListener) {
+    MEMORY_MAP.write().listeners.push(listener);
+}
+
+/// 在持有读锁的情况下访问全局内存布局
+pub fn with_memory_map<F, R>(f: F) -> R where F: FnOnce(&MemorySpace) -> R {
+    f(&MEMORY_MAP.read().space)
+}
+
+/// 在一次读锁内复制全局内存布局中的所有区域
+pub(crate) fn areas() -> Vec<MemoryArea> {
+    MEMORY_MAP.read().space.space.clone()
+}
+
+fn modify<F>(range: Range<u64>, ty: MemoryType, f: F) -> Result<MemoryArea, MemoryMapError>
+    where F: FnOnce(&mut MemorySpace, Range<u64>, MemoryType) {
+    if range.start >= range.end {
+        return Err(MemoryMapError::EmptyRange);
+    }
+    let mut map = MEMORY_MAP.write();
+    if !map.initialized {
+        return Err(MemoryMapError::NotInitialized);
+    }
+    let area = MemoryArea::new(range.start, range.end, ty, range.end - range.start);
+    f(&mut map.space, range, ty);
+    Ok(area)
+}
+
+fn notify_current(event: MemoryMapEvent) {
+    let listeners = MEMORY_MAP.read().listeners.clone();
+    notify(&listeners, event);
+}
+
+fn notify(listeners: &[MemoryMapListener], event: MemoryMapEvent) {
+    for listener in listeners {
+        listener(&event);
+    }
+}
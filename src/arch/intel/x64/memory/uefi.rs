@@ -0,0 +1,231 @@
+///! 从UEFI内存布局（`EFI_MEMORY_DESCRIPTOR`数组）中导入内存布局
+///! 内存布局由`GetMemoryMap`返回，每一项的大小为`descriptor_size`，可能大于结构本身的大小
+use core::mem::size_of;
+use core::ptr;
+
+use bitflags::bitflags;
+
+use crate::arch::intel::x64::memory::{MemorySpace, MemoryType};
+
+/// UEFI规范定义的页面大小，与CPU使用的页面大小无关
+pub const UEFI_PAGE_SIZE: u64 = 0x1000;
+/// 当前UEFI规范中`EFI_MEMORY_DESCRIPTOR`的版本
+pub const UEFI_DESCRIPTOR_VERSION: u32 = 1;
+
+bitflags! {
+    /// `EFI_MEMORY_DESCRIPTOR`的Attribute字段
+    pub struct UefiMemoryAttribute: u64 {
+        /// 支持不可缓存
+        const UC =              1 << 0;
+        /// 支持写合并
+        const WC =              1 << 1;
+        /// 支持写透
+        const WT =              1 << 2;
+        /// 支持写回
+        const WB =              1 << 3;
+        /// 支持不可缓存并导出
+        const UCE =             1 << 4;
+        /// 支持写保护
+        const WP =              1 << 12;
+        /// 支持读保护
+        const RP =              1 << 13;
+        /// 支持执行保护
+        const XP =              1 << 14;
+        /// 非易失性内存
+        const NV =              1 << 15;
+        /// 更可靠的内存
+        const MORE_RELIABLE =   1 << 16;
+        /// 支持只读
+        const RO =              1 << 17;
+        /// 特定用途内存
+        const SP =              1 << 18;
+        /// 支持CPU加密
+        const CPU_CRYPTO =      1 << 19;
+        /// 该区域在`ExitBootServices`后依旧被UEFI运行时服务使用
+        const RUNTIME =         1 << 63;
+    }
+}
+
+/// UEFI内存类型
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+#[repr(u32)]
+pub enum UefiMemoryType {
+    Reserved = 0,
+    LoaderCode = 1,
+    LoaderData = 2,
+    BootServicesCode = 3,
+    BootServicesData = 4,
+    RuntimeServicesCode = 5,
+    RuntimeServicesData = 6,
+    Conventional = 7,
+    Unusable = 8,
+    ACPIReclaim = 9,
+    ACPINonVolatile = 10,
+    MMIO = 11,
+    MMIOPortSpace = 12,
+    PalCode = 13,
+    Persistent = 14,
+    Unaccepted = 15,
+}
+
+impl UefiMemoryType {
+    /// 从原始值转换，未知类型返回None
+    pub fn from_raw(ty: u32) -> Option<Self> {
+        let ty = match ty {
+            0 => UefiMemoryType::Reserved,
+            1 => UefiMemoryType::LoaderCode,
+            2 => UefiMemoryType::LoaderData,
+            3 => UefiMemoryType::BootServicesCode,
+            4 => UefiMemoryType::BootServicesData,
+            5 => UefiMemoryType::RuntimeServicesCode,
+            6 => UefiMemoryType::RuntimeServicesData,
+            7 => UefiMemoryType::Conventional,
+            8 => UefiMemoryType::Unusable,
+            9 => UefiMemoryType::ACPIReclaim,
+            10 => UefiMemoryType::ACPINonVolatile,
+            11 => UefiMemoryType::MMIO,
+            12 => UefiMemoryType::MMIOPortSpace,
+            13 => UefiMemoryType::PalCode,
+            14 => UefiMemoryType::Persistent,
+            15 => UefiMemoryType::Unaccepted,
+            _ => return None,
+        };
+        Some(ty)
+    }
+}
+
+/// 原始的`EFI_MEMORY_DESCRIPTOR`结构
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+struct RawDescriptor {
+    ty: u32,
+    _pad: u32,
+    phys_start: u64,
+    virt_start: u64,
+    page_count: u64,
+    attribute: u64,
+}
+
+/// 解析后的UEFI内存描述符
+#[derive(Debug, Copy, Clone)]
+pub struct UefiMemoryDescriptor {
+    /// 原始的内存类型，用于保留未知类型
+    pub raw_type: u32,
+    pub phys_start: u64,
+    pub virt_start: u64,
+    /// 区域包含的UEFI页面（4KB）个数
+    pub page_count: u64,
+    pub attribute: UefiMemoryAttribute,
+}
+
+impl UefiMemoryDescriptor {
+    /// UEFI内存类型，未知类型返回None
+    pub fn memory_type(&self) -> Option<UefiMemoryType> {
+        UefiMemoryType::from_raw(self.raw_type)
+    }
+
+    /// 区域的字节数
+    pub fn size(&self) -> u64 {
+        self.page_count.saturating_mul(UEFI_PAGE_SIZE)
+    }
+
+    /// 转为`MemoryType`
+    /// `ExitBootServices`后启动服务使用的内存可以被回收，加载器使用的内存通常包含内核映像，因此标记为已使用。
+    /// 设置了`RUNTIME`属性的区域始终被保留给运行时服务，
+    /// 但固件通常也会为运行时服务使用的MMIO区域设置`RUNTIME`属性，这些区域依旧作为MMIO处理
+    pub fn area_type(&self) -> MemoryType {
+        let ty = match self.memory_type() {
+            Some(ty) => ty,
+            None => return MemoryType::ReservedArea,
+        };
+        match ty {
+            UefiMemoryType::RuntimeServicesCode => MemoryType::UefiRunTimeCode,
+            UefiMemoryType::RuntimeServicesData => MemoryType::UefiRunTimeData,
+            UefiMemoryType::MMIO => MemoryType::MMIO,
+            UefiMemoryType::MMIOPortSpace => MemoryType::MMIOPortArea,
+            _ if self.attribute.contains(UefiMemoryAttribute::RUNTIME) => MemoryType::UefiRunTimeData,
+            UefiMemoryType::Conventional
+            | UefiMemoryType::BootServicesCode
+            | UefiMemoryType::BootServicesData => MemoryType::FreeArea,
+            UefiMemoryType::LoaderCode | UefiMemoryType::LoaderData => MemoryType::UsedArea,
+            UefiMemoryType::Unusable => MemoryType::Defective,
+            UefiMemoryType::ACPIReclaim => MemoryType::ACPIArea,
+            UefiMemoryType::ACPINonVolatile => MemoryType::ACPIReservedArea,
+            UefiMemoryType::Reserved
+            | UefiMemoryType::PalCode
+            | UefiMemoryType::Persistent
+            | UefiMemoryType::Unaccepted => MemoryType::ReservedArea,
+        }
+    }
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum UefiMemoryMapError {
+    /// 不支持的描述符版本
+    UnsupportedVersion(u32),
+    /// 描述符大小小于`EFI_MEMORY_DESCRIPTOR`
+    DescriptorTooSmall(usize),
+    /// 缓冲区大小不是描述符大小的整数倍
+    InvalidBufferSize(usize),
+}
+
+/// 遍历UEFI内存布局中的描述符
+#[derive(Clone)]
+pub struct UefiDescriptorIter<'a> {
+    buf: &'a [u8],
+    descriptor_size: usize,
+}
+
+impl<'a> UefiDescriptorIter<'a> {
+    /// 使用`GetMemoryMap`返回的缓冲区，描述符大小和版本创建
+    /// 描述符按照`descriptor_size`步进，新版本在结构末尾增加的字段会被忽略
+    pub fn new(buf: &'a [u8], descriptor_size: usize, descriptor_version: u32) -> Result<Self, UefiMemoryMapError> {
+        if descriptor_version < UEFI_DESCRIPTOR_VERSION {
+            return Err(UefiMemoryMapError::UnsupportedVersion(descriptor_version));
+        }
+        if descriptor_size < size_of::<RawDescriptor>() {
+            return Err(UefiMemoryMapError::DescriptorTooSmall(descriptor_size));
+        }
+        if buf.len() % descriptor_size != 0 {
+            return Err(UefiMemoryMapError::InvalidBufferSize(buf.len()));
+        }
+        Ok(Self { buf, descriptor_size })
+    }
+}
+
+impl<'a> Iterator for UefiDescriptorIter<'a> {
+    type Item = UefiMemoryDescriptor;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buf.len() < self.descriptor_size {
+            return None;
+        }
+        // 缓冲区不保证按8字节对齐
+        let raw = unsafe { ptr::read_unaligned(self.buf.as_ptr() as *const RawDescriptor) };
+        self.buf = &self.buf[self.descriptor_size..];
+        Some(UefiMemoryDescriptor {
+            raw_type: raw.ty,
+            phys_start: raw.phys_start,
+            virt_start: raw.virt_start,
+            page_count: raw.page_count,
+            attribute: UefiMemoryAttribute::from_bits_truncate(raw.attribute),
+        })
+    }
+}
+
+impl MemorySpace {
+    /// 根据UEFI内存布局创建规范化后的内存布局
+    pub fn from_uefi(buf: &[u8], descriptor_size: usize, descriptor_version: u32) -> Result<Self, UefiMemoryMapError> {
+        let mut space = MemorySpace::new();
+        for descriptor in UefiDescriptorIter::new(buf, descriptor_size, descriptor_version)? {
+            let size = descriptor.size();
+            if size == 0 {
+                continue;
+            }
+            let end = descriptor.phys_start.saturating_add(size);
+            space.add_area(descriptor.phys_start, end, descriptor.area_type(), size);
+        }
+        space.normalize();
+        Ok(space)
+    }
+}
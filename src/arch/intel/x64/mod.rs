@@ -8,6 +8,11 @@ pub mod address;
 pub mod paging;
 pub mod memory;
 pub mod descriptor;
+pub mod elf;
+pub mod backtrace;
+pub mod boot;
+#[cfg(feature = "entry")]
+pub mod entry;
 
 /// 名称 	功能描述
 /// Index 	用于索引目标段描述符
@@ -24,6 +29,16 @@ impl SegmentSelector {
     pub fn new(index: u16, rpl: PrivilegedLevel) -> SegmentSelector {
         SegmentSelector(index << 3 | (rpl as u16))
     }
+
+    /// 使用给定的索引和特权级创建指向LDT的段选择子（TI=1）
+    pub fn new_local(index: u16, rpl: PrivilegedLevel) -> SegmentSelector {
+        SegmentSelector(index << 3 | 1 << 2 | (rpl as u16))
+    }
+
+    /// 段选择子是否指向LDT
+    pub fn is_local(&self) -> bool {
+        self.0.get_bit(2)
+    }
 }
 
 impl From<u16> for SegmentSelector {
@@ -20,6 +20,32 @@ pub unsafe trait FrameAllocator<S: PageSize> {
     fn dealloc_size(&mut self, frame: Frame, count: usize);
 }
 
+unsafe impl<'a, S: PageSize, A: FrameAllocator<S> + ?Sized> FrameAllocator<S> for &'a mut A {
+    fn alloc(&mut self) -> Option<UnusedFrame<S>> {
+        (**self).alloc()
+    }
+
+    fn dealloc(&mut self, frame: UnusedFrame<S>) {
+        (**self).dealloc(frame)
+    }
+
+    fn free_frames(&self) -> usize {
+        (**self).free_frames()
+    }
+
+    fn used_frames(&self) -> usize {
+        (**self).used_frames()
+    }
+
+    fn alloc_size(&mut self, size: Layout) -> Option<UnusedFrame<S>> {
+        (**self).alloc_size(size)
+    }
+
+    fn dealloc_size(&mut self, frame: Frame, count: usize) {
+        (**self).dealloc_size(frame, count)
+    }
+}
+
 #[derive(Debug)]
 pub struct UnusedFrame<S: PageSize = Page4KB>(Frame<S>);
 
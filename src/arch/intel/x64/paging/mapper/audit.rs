@@ -0,0 +1,172 @@
+///! 页表安全检查
+///! 遍历当前地址空间中所有已映射的页面，按照页表各级的有效权限报告违反安全策略的映射
+use alloc::vec::Vec;
+
+use crate::arch::intel::chips::flags::EferFlags;
+use crate::arch::intel::chips::msr_set::Efer;
+use crate::arch::intel::x64::address::{VirtAddr, VirtualAddress};
+use crate::arch::intel::x64::paging::{ENTRY_COUNT, Level1, Level2, Level3, Page1GB, Page2MB, Page4KB, PageIndex, PageSize, PageTable, TableLevel};
+use crate::arch::intel::x64::paging::flags::PageTableFlags;
+use crate::arch::intel::x64::paging::mapper::{ACCESS_FLAGS, effective_flags, RecursivePageTable};
+
+/// 内核空间（高半部分Canonical地址）的起始地址
+pub const KERNEL_SPACE_START: u64 = 0xFFFF_8000_0000_0000;
+
+/// 违反的安全策略
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum AuditViolation {
+    /// 页面同时可写可执行（W^X）
+    WritableExecutable,
+    /// 内核空间的页面设置了`USER_ACCESSIBLE`
+    KernelUserAccessible,
+    /// 内核空间的页面没有设置`GLOBAL`
+    KernelNotGlobal,
+    /// 页表项设置了`NO_EXECUTE`，但是`EFER.NXE`没有启用，访问该页面会产生保留位错误
+    NoExecuteWithoutNxe,
+}
+
+/// 一段违反安全策略的连续虚拟地址范围
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct AuditFinding {
+    /// 起始地址
+    pub start: VirtAddr,
+    /// 范围的大小
+    pub size: u64,
+    /// 综合各级页表项后的有效页面属性
+    pub flags: PageTableFlags,
+    /// 违反的安全策略
+    pub violation: AuditViolation,
+}
+
+/// 需要检查的安全策略，默认检查所有策略
+#[derive(Debug, Copy, Clone)]
+pub struct AuditPolicy {
+    pub writable_executable: bool,
+    pub kernel_user_accessible: bool,
+    pub kernel_not_global: bool,
+    pub no_execute_without_nxe: bool,
+}
+
+impl Default for AuditPolicy {
+    fn default() -> Self {
+        Self {
+            writable_executable: true,
+            kernel_user_accessible: true,
+            kernel_not_global: true,
+            no_execute_without_nxe: true,
+        }
+    }
+}
+
+/// 检查当前地址空间中所有已映射的页面，递归项映射的页表本身不会被检查
+///
+/// 页面的有效权限由各级页表项共同决定：`WRITABLE`和`USER_ACCESSIBLE`需要每一级都设置，
+/// 任意一级设置`NO_EXECUTE`即不可执行，`GLOBAL`只看映射页面的页表项。
+/// 相邻且违反同一策略、有效属性相同的页面会合并为一条结果
+pub fn audit(table: &RecursivePageTable, policy: AuditPolicy) -> Vec<AuditFinding> {
+    let nxe = Efer::new().read().contains(EferFlags::NO_EXECUTE_ENABLE);
+    let mut auditor = Auditor { policy, nxe, findings: Vec::new() };
+    let recursive_index = table.recursive_index;
+    let p4: &PageTable = &*table.p4;
+
+    for i in 0..ENTRY_COUNT as u16 {
+        let index = PageIndex::new(i);
+        if index == recursive_index {
+            continue;
+        }
+        let flags = effective_flags(ACCESS_FLAGS, p4.entry_flags(index));
+        // 高半部分地址需要进行符号扩展
+        let mut base = u64::from(i) << 39;
+        if i >= 256 {
+            base |= 0xFFFF_0000_0000_0000;
+        }
+        // 4级页表始终位于递归地址上
+        if let Some(p3) = unsafe { p4.next_table(index) } {
+            auditor.walk_p3(p3, base, flags);
+        }
+    }
+    auditor.findings
+}
+
+struct Auditor {
+    policy: AuditPolicy,
+    nxe: bool,
+    findings: Vec<AuditFinding>,
+}
+
+impl Auditor {
+    /// 遍历3级页表，`parent`为4级页表项的有效属性
+    fn walk_p3(&mut self, p3: &PageTable<Level3>, base: u64, parent: PageTableFlags) {
+        for (index, start, flags) in present_entries(p3, base, Page1GB::P_SIZE, parent) {
+            if Level3::is_huge(&p3[index]) {
+                self.check(start, Page1GB::P_SIZE, flags);
+            } else if let Some(p2) = unsafe { p3.next_table(index) } {
+                self.walk_p2(p2, start, flags);
+            }
+        }
+    }
+
+    /// 遍历2级页表
+    fn walk_p2(&mut self, p2: &PageTable<Level2>, base: u64, parent: PageTableFlags) {
+        for (index, start, flags) in present_entries(p2, base, Page2MB::P_SIZE, parent) {
+            if Level2::is_huge(&p2[index]) {
+                self.check(start, Page2MB::P_SIZE, flags);
+            } else if let Some(p1) = unsafe { p2.next_table(index) } {
+                self.walk_p1(p1, start, flags);
+            }
+        }
+    }
+
+    /// 遍历1级页表
+    fn walk_p1(&mut self, p1: &PageTable<Level1>, base: u64, parent: PageTableFlags) {
+        for (_, start, flags) in present_entries(p1, base, Page4KB::P_SIZE, parent) {
+            self.check(start, Page4KB::P_SIZE, flags);
+        }
+    }
+
+    fn check(&mut self, start: u64, size: u64, flags: PageTableFlags) {
+        let kernel = start >= KERNEL_SPACE_START;
+        if self.policy.writable_executable
+            && flags.contains(PageTableFlags::WRITABLE)
+            && !flags.contains(PageTableFlags::NO_EXECUTE) {
+            self.report(start, size, flags, AuditViolation::WritableExecutable);
+        }
+        if self.policy.kernel_user_accessible && kernel && flags.contains(PageTableFlags::USER_ACCESSIBLE) {
+            self.report(start, size, flags, AuditViolation::KernelUserAccessible);
+        }
+        if self.policy.kernel_not_global && kernel && !flags.contains(PageTableFlags::GLOBAL) {
+            self.report(start, size, flags, AuditViolation::KernelNotGlobal);
+        }
+        if self.policy.no_execute_without_nxe && !self.nxe && flags.contains(PageTableFlags::NO_EXECUTE) {
+            self.report(start, size, flags, AuditViolation::NoExecuteWithoutNxe);
+        }
+    }
+
+    fn report(&mut self, start: u64, size: u64, flags: PageTableFlags, violation: AuditViolation) {
+        // 与同一策略的上一条结果相邻时合并
+        if let Some(last) = self.findings.iter_mut().rev().find(|f| f.violation == violation) {
+            if last.flags == flags && last.start.as_u64().wrapping_add(last.size) == start {
+                last.size += size;
+                return;
+            }
+        }
+        self.findings.push(AuditFinding {
+            start: VirtAddr::new(start),
+            size,
+            flags,
+            violation,
+        });
+    }
+}
+
+/// 返回页表中所有存在的页表项的索引、映射的起始地址以及有效属性
+fn present_entries<L: TableLevel>(table: &PageTable<L>, base: u64, entry_size: u64, parent: PageTableFlags)
+                                  -> impl Iterator<Item=(PageIndex, u64, PageTableFlags)> + '_ {
+    (0..ENTRY_COUNT as u16)
+        .map(PageIndex::new)
+        .filter(move |index| table[*index].flags().contains(PageTableFlags::PRESENT))
+        .map(move |index| {
+            let start = base + u64::from(u16::from(index)) * entry_size;
+            (index, start, effective_flags(parent, table.entry_flags(index)))
+        })
+}
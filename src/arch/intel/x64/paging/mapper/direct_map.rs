@@ -0,0 +1,113 @@
+///! 根据内存布局建立物理内存直接映射
+///! 物理地址`paddr`被映射到`offset + paddr`，尽可能使用1GB和2MB的大页面
+use raw_cpuid::CpuId;
+
+use crate::arch::intel::chips::msr_set::Efer;
+use crate::arch::intel::x64::address::{align_down, align_up, PhysAddr, PhysicalAddress, VirtAddr, VirtualAddress};
+use crate::arch::intel::x64::memory::{MemorySpace, MemoryType};
+use crate::arch::intel::x64::paging::{Frame, FrameAllocator, Page, Page1GB, Page2MB, Page4KB, PageSize};
+use crate::arch::intel::x64::paging::flags::PageTableFlags;
+use crate::arch::intel::x64::paging::mapper::{MapAllSize, Mapper, PhysOffset};
+use crate::arch::intel::x64::paging::result::{DirectMapError, MapToError};
+
+/// 直接映射使用的页面属性，缓存类型由`cache_flags`根据内存类型给出
+/// `EFER.NXE`开启时还会额外设置`NO_EXECUTE`
+pub const DIRECT_MAP_FLAGS: PageTableFlags = PageTableFlags::from_bits_truncate(
+    PageTableFlags::PRESENT.bits()
+        | PageTableFlags::WRITABLE.bits()
+        | PageTableFlags::GLOBAL.bits()
+);
+
+/// 返回内存类型在直接映射中使用的缓存类型，不需要映射的类型返回None
+/// 缓存类型按照默认的PAT配置选择：`FreeArea`和`ACPIArea`使用WB，`MMIO`使用UC
+pub fn cache_flags(ty: MemoryType) -> Option<PageTableFlags> {
+    match ty {
+        MemoryType::FreeArea | MemoryType::ACPIArea => Some(PageTableFlags::empty()),
+        MemoryType::MMIO => Some(PageTableFlags::NO_CACHE | PageTableFlags::WRITE_THROUGH),
+        _ => None,
+    }
+}
+
+/// 将`space`中所有`FreeArea`，`ACPIArea`以及`MMIO`区域映射到`offset`开始的虚拟地址
+/// 物理地址与虚拟地址同时对齐时使用大页面，CPU不支持1GB页面时最大使用2MB页面
+/// 已经以相同方式映射的部分会被跳过，返回该偏移量对应的`PhysOffset`
+///
+/// 新建立的映射之前不存在，因此不需要刷新TLB
+///
+/// # Safety
+/// `offset`开始的虚拟地址范围不能与其他映射冲突，`mapper`必须能够修改目标页表
+pub unsafe fn map_physical_memory<M, A>(mapper: &mut M, space: &MemorySpace, offset: VirtAddr, allocator: &mut A)
+                                        -> Result<PhysOffset, DirectMapError>
+    where M: MapAllSize, A: FrameAllocator<Page4KB> {
+    if offset.as_u64() % Page4KB::P_SIZE != 0 {
+        return Err(DirectMapError::NotAligned(offset));
+    }
+    let huge_1gb = CpuId::new()
+        .get_extended_function_info()
+        .map_or(false, |info| info.has_1gib_pages());
+    let mut flags = DIRECT_MAP_FLAGS;
+    flags.set(PageTableFlags::NO_EXECUTE, Efer::new().is_no_execute_enabled());
+
+    for area in space.iter() {
+        let cache = match cache_flags(area.ty) {
+            Some(cache) => cache,
+            None => continue,
+        };
+        let start = align_down(area.start_addr, Page4KB::P_SIZE);
+        let end = align_up(area.start_addr.saturating_add(area.length), Page4KB::P_SIZE);
+        map_region(mapper, start, end, offset, flags | cache, huge_1gb, allocator)?;
+    }
+    Ok(PhysOffset::new(offset))
+}
+
+/// 映射物理地址范围`[start, end)`
+unsafe fn map_region<M, A>(mapper: &mut M, start: u64, end: u64, offset: VirtAddr, flags: PageTableFlags,
+                           huge_1gb: bool, allocator: &mut A) -> Result<(), DirectMapError>
+    where M: MapAllSize, A: FrameAllocator<Page4KB> {
+    let mut current = start;
+    while current < end {
+        // `try_new`会对第47位进行符号扩展，结果必须与原始的和相同，否则说明跨越了非Canonical地址空洞
+        let virt = offset.as_u64().checked_add(current)
+            .and_then(|addr| VirtAddr::try_new(addr).ok().filter(|virt| virt.as_u64() == addr))
+            .ok_or_else(|| DirectMapError::AddressOverflow(PhysAddr::new(current)))?;
+
+        // 已经映射到相同物理地址的页面直接跳过
+        if let Some(phys) = mapper.translate_addr(virt) {
+            if phys.as_u64() != current {
+                return Err(DirectMapError::Conflict(virt));
+            }
+            current += Page4KB::P_SIZE;
+            continue;
+        }
+
+        let remain = end - current;
+        let aligned = |size: u64| current % size == 0 && virt.as_u64() % size == 0 && remain >= size;
+        if huge_1gb && aligned(Page1GB::P_SIZE) && map_page::<M, A, Page1GB>(mapper, current, virt, flags, allocator)? {
+            current += Page1GB::P_SIZE;
+        } else if aligned(Page2MB::P_SIZE) && map_page::<M, A, Page2MB>(mapper, current, virt, flags, allocator)? {
+            current += Page2MB::P_SIZE;
+        } else if map_page::<M, A, Page4KB>(mapper, current, virt, flags, allocator)? {
+            current += Page4KB::P_SIZE;
+        } else {
+            return Err(DirectMapError::Conflict(virt));
+        }
+    }
+    Ok(())
+}
+
+/// 映射一个大小为`S`的页面，页面范围内已有映射时返回false
+unsafe fn map_page<M, A, S>(mapper: &mut M, phys: u64, virt: VirtAddr, flags: PageTableFlags, allocator: &mut A)
+                            -> Result<bool, DirectMapError>
+    where M: Mapper<S>, A: FrameAllocator<Page4KB>, S: PageSize {
+    let page = Page::<S>::from_start_address(virt).unwrap();
+    let frame = Frame::<S>::from_start_addr(PhysAddr::new(phys)).unwrap();
+    match mapper.map_to(page, frame, flags, allocator) {
+        Ok(flush) => {
+            flush.ignore();
+            Ok(true)
+        }
+        Err(MapToError::PageAlreadyMapped(_)) => Ok(false),
+        Err(MapToError::ParentEntryHugePage) => Err(DirectMapError::Conflict(virt)),
+        Err(MapToError::FrameAllocateFailed) => Err(DirectMapError::FrameAllocateFailed),
+    }
+}
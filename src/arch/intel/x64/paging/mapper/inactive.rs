@@ -0,0 +1,104 @@
+///! 通过临时映射编辑未激活的页表
+///! 将未激活的4级页表安装到当前页表的临时项中，并在未激活页表中建立同一索引的递归项，
+///! 这样无需切换CR3或建立物理内存直接映射即可使用完整的`Mapper`编辑新页表
+use crate::arch::intel::chips::control::CR3;
+use crate::arch::intel::instructions::page_table::{flush, flush_all};
+use crate::arch::intel::x64::address::VirtualAddress;
+use crate::arch::intel::x64::paging::{ENTRY_COUNT, Frame, Page, PageIndex, PageTable, PageTableEntry};
+use crate::arch::intel::x64::paging::flags::PageTableFlags;
+use crate::arch::intel::x64::paging::mapper::RecursivePageTable;
+use crate::arch::intel::x64::paging::result::InactiveTableError;
+
+/// 未激活页表的编辑器
+///
+/// 假设当前页表的递归项为`R`，临时项为`S`，创建时
+/// 1. 当前4级页表的第`S`项指向未激活的4级页表，此时未激活的4级页表可以通过`0o_RRR_RRR_RRR_SSS_0000`访问
+/// 2. 未激活4级页表的第`S`项指向其自身，原有的页表项被保存
+///
+/// 之后`0o_SSS_SSS_SSS_SSS_0000`即为未激活4级页表的递归地址，可以构造以`S`为递归项的`RecursivePageTable`
+/// 编辑器被Drop时恢复两个页表项并刷新TLB
+///
+/// 编辑期间未激活页表中第`S`项原有的映射不可访问，不能映射或修改4级索引为`S`的页面
+pub struct InactivePageTableEditor<'a, 'b> {
+    active: &'a mut RecursivePageTable<'b>,
+    scratch_index: PageIndex,
+    saved_entry: PageTableEntry,
+}
+
+impl<'a, 'b> InactivePageTableEditor<'a, 'b> {
+    /// 使用当前4级页表中最后一个空闲项作为临时项编辑`p4_frame`所指向的4级页表
+    ///
+    /// # Safety
+    /// `p4_frame`必须是一个有效的4级页表，编辑期间不能有其他CPU使用当前页表访问临时项所在的地址范围
+    pub unsafe fn new(active: &'a mut RecursivePageTable<'b>, p4_frame: Frame) -> Result<Self, InactiveTableError> {
+        let recursive_index = active.recursive_index;
+        let index = (0..ENTRY_COUNT as u16).rev()
+            .map(PageIndex::new)
+            .find(|index| *index != recursive_index && active.p4[*index].is_unused())
+            .ok_or(InactiveTableError::NoFreeEntry)?;
+        Self::with_scratch_index(active, p4_frame, index)
+    }
+
+    /// 使用当前4级页表中指定的空闲项作为临时项编辑`p4_frame`所指向的4级页表
+    ///
+    /// # Safety
+    /// 同`InactivePageTableEditor::new`
+    pub unsafe fn with_scratch_index(active: &'a mut RecursivePageTable<'b>, p4_frame: Frame, index: PageIndex)
+                                     -> Result<Self, InactiveTableError> {
+        if index == active.recursive_index {
+            return Err(InactiveTableError::RecursiveIndex);
+        }
+        if !active.p4[index].is_unused() {
+            return Err(InactiveTableError::ScratchEntryInUse);
+        }
+        if CR3::read().0 == p4_frame {
+            return Err(InactiveTableError::TableIsActive);
+        }
+
+        // 不存在的页表项不会被缓存，安装临时项后只需要刷新访问未激活4级页表的地址
+        active.p4[index].set_frame(p4_frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+        let p4_page = Self::inactive_p4_page(active.recursive_index, index);
+        flush(p4_page.start_address());
+
+        let inactive_p4 = &mut *(p4_page.start_address().as_mut_ptr::<PageTable>());
+        let saved_entry = inactive_p4[index];
+        inactive_p4[index].set_frame(p4_frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+        // 临时项对应的地址范围此前没有映射，这里刷新整个TLB以清除分页结构缓存
+        flush_all();
+
+        Ok(Self {
+            active,
+            scratch_index: index,
+            saved_entry,
+        })
+    }
+
+    /// 使用的临时项索引
+    pub fn scratch_index(&self) -> PageIndex {
+        self.scratch_index
+    }
+
+    /// 返回用于编辑未激活页表的`Mapper`，返回的`Mapper`的生命周期不会超过编辑器
+    pub fn mapper(&mut self) -> RecursivePageTable<'_> {
+        let index = self.scratch_index;
+        let page = Page::from_page_table_indices(index, index, index, index);
+        unsafe { RecursivePageTable::new_unchecked(&mut *(page.start_address().as_mut_ptr::<PageTable>()), index) }
+    }
+
+    fn inactive_p4_page(recursive_index: PageIndex, scratch_index: PageIndex) -> Page {
+        Page::from_page_table_indices(recursive_index, recursive_index, recursive_index, scratch_index)
+    }
+}
+
+impl<'a, 'b> Drop for InactivePageTableEditor<'a, 'b> {
+    fn drop(&mut self) {
+        let p4_page = Self::inactive_p4_page(self.active.recursive_index, self.scratch_index);
+        unsafe {
+            let inactive_p4 = &mut *(p4_page.start_address().as_mut_ptr::<PageTable>());
+            inactive_p4[self.scratch_index] = self.saved_entry;
+            self.active.p4[self.scratch_index].set_unused();
+            // 编辑期间临时项范围内的页表都可能被缓存，需要刷新整个TLB
+            flush_all();
+        }
+    }
+}
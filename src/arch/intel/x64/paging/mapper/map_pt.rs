@@ -1,13 +1,9 @@
-use crate::bits::PageTableFlags;
-use crate::ia_32e::cpu::control::CR3;
-use crate::ia_32e::paging::{PageTable, PageTableEntry};
-use crate::ia_32e::paging::allocator::{FrameAllocator, UnusedFrame};
-use crate::ia_32e::paging::frame::Frame;
-use crate::ia_32e::paging::mapper::{MapAllSize, Mapper, MapperFlush};
-use crate::ia_32e::paging::page::{Page, Page1GB, Page2MB, Page4KB};
-use crate::ia_32e::paging::result::{CreatePageTableError, FlagUpdateError, MapToError, PageTableWalkError, TranslateError, TranslationResult, UnmapError};
-use crate::ia_32e::paging::result::FrameError;
-use crate::ia_32e::VirtAddr;
+use crate::arch::intel::chips::control::CR3;
+use crate::arch::intel::x64::address::{PhysicalAddress, VirtAddr, VirtualAddress};
+use crate::arch::intel::x64::paging::{Frame, FrameAllocator, Level1, Level2, Level3, Page, Page1GB, Page2MB, Page4KB, PageTable, PageTableEntry, TableLevel, UnusedFrame};
+use crate::arch::intel::x64::paging::flags::PageTableFlags;
+use crate::arch::intel::x64::paging::mapper::{ACCESS_FLAGS, effective_flags, MapAllSize, Mapper, MapperFlush};
+use crate::arch::intel::x64::paging::result::{CreatePageTableError, FlagUpdateError, MapToError, PageTableWalkError, TranslateError, TranslationResult, UnmapError};
 
 /// 将给定的物理帧转换为页表裸指针
 pub trait PhysicalToVirtual {
@@ -37,17 +33,17 @@ impl<P: PhysicalToVirtual> PageTableWalker<P> {
     /// MappedPageTable内部辅助函数可获取对下一级页面表的引用。
     /// 如果未使用该条目，则返回 `PageTableWalkError::NotMapped`。
     /// 如果在传递的条目中设置了`HUGE_PAGE`标志，则返回`PageTableWalkError::MappedToHugePage`。
-    fn next_table<'a>(&self, entry: &'a PageTableEntry) -> Result<&'a PageTable, PageTableWalkError> {
-        let table_ptr = self.phy_to_vir.phy_to_vir(entry.frame()?);
-        let page_table: &PageTable = unsafe { &*table_ptr };
+    fn next_table<'a, L: TableLevel>(&self, entry: &'a PageTableEntry) -> Result<&'a PageTable<L>, PageTableWalkError> {
+        let table_ptr = self.phy_to_vir.phy_to_vir(entry.frame()?) as *mut PageTable<L>;
+        let page_table: &PageTable<L> = unsafe { &*table_ptr };
         Ok(page_table)
     }
     /// MappedPageTable内部辅助函数可获取对下一级页面表的可变引用。
     /// 如果未使用该条目，则返回 `PageTableWalkError::NotMapped`。
     /// 如果在传递的条目中设置了`HUGE_PAGE`标志，则返回`PageTableWalkError::MappedToHugePage`。
-    fn next_table_mut<'a>(&self, entry: &'a mut PageTableEntry) -> Result<&'a mut PageTable, PageTableWalkError> {
-        let table_ptr = self.phy_to_vir.phy_to_vir(entry.frame()?);
-        let page_table: &mut PageTable = unsafe { &mut *table_ptr };
+    fn next_table_mut<'a, L: TableLevel>(&self, entry: &'a mut PageTableEntry) -> Result<&'a mut PageTable<L>, PageTableWalkError> {
+        let table_ptr = self.phy_to_vir.phy_to_vir(entry.frame()?) as *mut PageTable<L>;
+        let page_table: &mut PageTable<L> = unsafe { &mut *table_ptr };
         Ok(page_table)
     }
 
@@ -56,8 +52,8 @@ impl<P: PhysicalToVirtual> PageTableWalker<P> {
     /// 如果传递的`entry`已被映射，则直接返回下一个表。
     /// 如果`entry`未使用并且分配器返回`None`，则返回`MapToError::FrameAllocationFailed`。
     /// 如果在传递的条目中设置了`HUGE_PAGE`标志，则返回`MapToError::ParentEntryHugePage`。
-    fn create_next_table<'a, A>(&self, entry: &'a mut PageTableEntry, allocator: &mut A) -> Result<&'a mut PageTable, CreatePageTableError>
-        where A: FrameAllocator<Page4KB> {
+    fn create_next_table<'a, L, A>(&self, entry: &'a mut PageTableEntry, allocator: &mut A) -> Result<&'a mut PageTable<L>, CreatePageTableError>
+        where L: TableLevel, A: FrameAllocator<Page4KB> {
         let mut created = false;
         // 如果当前entry没有被使用可以创建新的entry
         if entry.is_unused() {
@@ -71,7 +67,7 @@ impl<P: PhysicalToVirtual> PageTableWalker<P> {
         }
         let pt = match self.next_table_mut(entry) {
             Ok(table) => table,
-            Err(PageTableWalkError::MappedToHugePage) => Err(CreatePageTableError::MappedToHugePage)?,
+            Err(PageTableWalkError::MappedToHugePage) => return Err(CreatePageTableError::MappedToHugePage),
             Err(PageTableWalkError::NotMapped) => panic!("entry should be mapped at this point"),
         };
         if created {
@@ -97,10 +93,10 @@ impl<'a, P: PhysicalToVirtual> MappedPageTable<'a, P> {
         }
     }
 
+    /// 使用CR3中的4级页表创建，4级页表通过`phy_to_vir`访问
     pub unsafe fn from_cr3(phy_to_vir: P) -> Self {
         let (frame, _) = CR3::read();
-        let pt = frame.start_address().as_u64() as *mut PageTable;
-        let pml4t = &mut *pt;
+        let pml4t = &mut *phy_to_vir.phy_to_vir(frame);
         Self {
             pt_walker: PageTableWalker::new(phy_to_vir),
             level_4_table: pml4t,
@@ -108,51 +104,51 @@ impl<'a, P: PhysicalToVirtual> MappedPageTable<'a, P> {
     }
 
     // 根据给定的帧和页面进行1gb页面映射
-    unsafe fn map_to_1g<A>(&mut self, page: Page<Page1GB>, frame: UnusedFrame<Page1GB>, flags: PageTableFlags, allocator: &mut A)
+    unsafe fn map_to_1g<A>(&mut self, page: Page<Page1GB>, frame: Frame<Page1GB>, flags: PageTableFlags, allocator: &mut A)
                            -> Result<MapperFlush<Page1GB>, MapToError<Page1GB>>
         where A: FrameAllocator<Page4KB> {
         let p4 = &mut self.level_4_table;
-        let p3 = self.pt_walker.create_next_table(&mut p4[page.p4_index()], allocator)?;
+        let p3: &mut PageTable<Level3> = self.pt_walker.create_next_table(&mut p4[page.p4_index()], allocator)?;
         // 将frame与页面做映射
         if !p3[page.p3_index()].is_unused() {
-            return Err(MapToError::PageAlreadyMapped(frame));
+            return Err(MapToError::PageAlreadyMapped(UnusedFrame::new(frame)));
         }
         p3[page.p3_index()].set_addr(frame.start_address(), flags | PageTableFlags::HUGE_PAGE);
         Ok(MapperFlush::new(page))
     }
     // 根据给定的帧和页面进行2mb页面映射
-    unsafe fn map_to_2mb<A>(&mut self, page: Page<Page2MB>, frame: UnusedFrame<Page2MB>, flags: PageTableFlags, allocator: &mut A)
+    unsafe fn map_to_2mb<A>(&mut self, page: Page<Page2MB>, frame: Frame<Page2MB>, flags: PageTableFlags, allocator: &mut A)
                             -> Result<MapperFlush<Page2MB>, MapToError<Page2MB>>
         where A: FrameAllocator<Page4KB> {
         let p4 = &mut self.level_4_table;
         // 创建3级页表
-        let p3 = self.pt_walker.create_next_table(&mut p4[page.p4_index()], allocator)?;
+        let p3: &mut PageTable<Level3> = self.pt_walker.create_next_table(&mut p4[page.p4_index()], allocator)?;
         // 创建2级页表
-        let p2 = self.pt_walker.create_next_table(&mut p3[page.p3_index()], allocator)?;
+        let p2: &mut PageTable<Level2> = self.pt_walker.create_next_table(&mut p3[page.p3_index()], allocator)?;
         // 将frame与页面做映射
         if !p2[page.p2_index()].is_unused() {
-            return Err(MapToError::PageAlreadyMapped(frame));
+            return Err(MapToError::PageAlreadyMapped(UnusedFrame::new(frame)));
         }
         p2[page.p2_index()].set_addr(frame.start_address(), flags | PageTableFlags::HUGE_PAGE);
 
         Ok(MapperFlush::new(page))
     }
     // 根据给定的帧和页面进行4kb页面映射
-    unsafe fn map_to_4kb<A>(&mut self, page: Page<Page4KB>, frame: UnusedFrame<Page4KB>, flags: PageTableFlags, allocator: &mut A)
+    unsafe fn map_to_4kb<A>(&mut self, page: Page<Page4KB>, frame: Frame<Page4KB>, flags: PageTableFlags, allocator: &mut A)
                             -> Result<MapperFlush<Page4KB>, MapToError<Page4KB>>
         where A: FrameAllocator<Page4KB> {
         let p4 = &mut self.level_4_table;
         // 创建3级页表
-        let p3 = self.pt_walker.create_next_table(&mut p4[page.p4_index()], allocator)?;
+        let p3: &mut PageTable<Level3> = self.pt_walker.create_next_table(&mut p4[page.p4_index()], allocator)?;
         // 创建2级页表
-        let p2 = self.pt_walker.create_next_table(&mut p3[page.p3_index()], allocator)?;
+        let p2: &mut PageTable<Level2> = self.pt_walker.create_next_table(&mut p3[page.p3_index()], allocator)?;
         // 创建1级页表
-        let p1 = self.pt_walker.create_next_table(&mut p2[page.p2_index()], allocator)?;
+        let p1: &mut PageTable<Level1> = self.pt_walker.create_next_table(&mut p2[page.p2_index()], allocator)?;
 
         if !p1[page.p1_index()].is_unused() {
-            return Err(MapToError::PageAlreadyMapped(frame));
+            return Err(MapToError::PageAlreadyMapped(UnusedFrame::new(frame)));
         }
-        p1[page.p1_index()].set_frame(frame.frame(), flags);
+        p1[page.p1_index()].set_frame(frame, flags);
 
         Ok(MapperFlush::new(page))
     }
@@ -162,23 +158,25 @@ impl<'a, P: PhysicalToVirtual> MappedPageTable<'a, P> {
 ///// Mapping Memory
 /////////////////////
 
+/// `update_flags`和`unmap`不会拆分大页面，目标页面被2MB或1GB页面覆盖时返回`ParentEntryHugePage`
 impl<'a, P: PhysicalToVirtual> Mapper<Page4KB> for MappedPageTable<'a, P> {
-    unsafe fn map_to<A>(&mut self, page: Page<Page4KB>, frame: UnusedFrame<Page4KB>, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush<Page4KB>, MapToError<Page4KB>> where A: FrameAllocator<Page4KB>, Self: Sized {
+    unsafe fn map_to<A>(&mut self, page: Page<Page4KB>, frame: Frame<Page4KB>, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush<Page4KB>, MapToError<Page4KB>> where A: FrameAllocator<Page4KB>, Self: Sized {
         self.map_to_4kb(page, frame, flags, allocator)
     }
 
     fn unmap(&mut self, page: Page<Page4KB>) -> Result<(Frame<Page4KB>, MapperFlush<Page4KB>), UnmapError> {
         let p4 = &mut self.level_4_table;
-        let p3 = self.pt_walker.next_table_mut(&mut p4[page.p3_index()])?;
-        let p2 = self.pt_walker.next_table_mut(&mut p3[page.p2_index()])?;
-        let p1 = self.pt_walker.next_table_mut(&mut p2[page.p1_index()])?;
+        let p3: &mut PageTable<Level3> = self.pt_walker.next_table_mut(&mut p4[page.p4_index()])?;
+        let p2: &mut PageTable<Level2> = self.pt_walker.next_table_mut(&mut p3[page.p3_index()])?;
+        let p1: &mut PageTable<Level1> = self.pt_walker.next_table_mut(&mut p2[page.p2_index()])?;
 
         let entry = &mut p1[page.p1_index()];
 
-        let frame = entry.frame().map_err(|err| match err {
-            FrameError::FrameNotPresent => UnmapError::PageNotMapped,
-            FrameError::HugeFrame => UnmapError::ParentEntryHugePage,
-        })?;
+        // 1级页表项的第7位表示PAT，不能使用会把该位当作大页面的`frame`
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
+            return Err(UnmapError::PageNotMapped);
+        }
+        let frame = Frame::include_address(entry.addr());
         entry.set_unused();
 
         Ok((frame, MapperFlush::new(page)))
@@ -186,11 +184,11 @@ impl<'a, P: PhysicalToVirtual> Mapper<Page4KB> for MappedPageTable<'a, P> {
 
     unsafe fn update_flags(&mut self, page: Page<Page4KB>, flags: PageTableFlags) -> Result<MapperFlush<Page4KB>, FlagUpdateError> {
         let p4 = &mut self.level_4_table;
-        let p3 = self.pt_walker.next_table_mut(&mut p4[page.p3_index()])?;
-        let p2 = self.pt_walker.next_table_mut(&mut p3[page.p2_index()])?;
-        let p1 = self.pt_walker.next_table_mut(&mut p2[page.p1_index()])?;
+        let p3: &mut PageTable<Level3> = self.pt_walker.next_table_mut(&mut p4[page.p4_index()])?;
+        let p2: &mut PageTable<Level2> = self.pt_walker.next_table_mut(&mut p3[page.p3_index()])?;
+        let p1: &mut PageTable<Level1> = self.pt_walker.next_table_mut(&mut p2[page.p2_index()])?;
 
-        if !p1[page.p1_index()].is_unused() {
+        if p1[page.p1_index()].is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
         }
 
@@ -201,9 +199,9 @@ impl<'a, P: PhysicalToVirtual> Mapper<Page4KB> for MappedPageTable<'a, P> {
 
     fn translate_page(&mut self, page: Page<Page4KB>) -> Result<Frame<Page4KB>, TranslateError> {
         let p4 = &mut self.level_4_table;
-        let p3 = self.pt_walker.next_table_mut(&mut p4[page.p3_index()])?;
-        let p2 = self.pt_walker.next_table_mut(&mut p3[page.p2_index()])?;
-        let p1 = self.pt_walker.next_table_mut(&mut p2[page.p1_index()])?;
+        let p3: &mut PageTable<Level3> = self.pt_walker.next_table_mut(&mut p4[page.p4_index()])?;
+        let p2: &mut PageTable<Level2> = self.pt_walker.next_table_mut(&mut p3[page.p3_index()])?;
+        let p1: &mut PageTable<Level1> = self.pt_walker.next_table_mut(&mut p2[page.p2_index()])?;
 
         let entry = p1[page.p1_index()];
 
@@ -216,14 +214,14 @@ impl<'a, P: PhysicalToVirtual> Mapper<Page4KB> for MappedPageTable<'a, P> {
 }
 
 impl<'a, P: PhysicalToVirtual> Mapper<Page2MB> for MappedPageTable<'a, P> {
-    unsafe fn map_to<A>(&mut self, page: Page<Page2MB>, frame: UnusedFrame<Page2MB>, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush<Page2MB>, MapToError<Page2MB>> where A: FrameAllocator<Page4KB>, Self: Sized {
+    unsafe fn map_to<A>(&mut self, page: Page<Page2MB>, frame: Frame<Page2MB>, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush<Page2MB>, MapToError<Page2MB>> where A: FrameAllocator<Page4KB>, Self: Sized {
         self.map_to_2mb(page, frame, flags, allocator)
     }
 
     fn unmap(&mut self, page: Page<Page2MB>) -> Result<(Frame<Page2MB>, MapperFlush<Page2MB>), UnmapError> {
         let p4 = &mut self.level_4_table;
-        let p3 = self.pt_walker.next_table_mut(&mut p4[page.p3_index()])?;
-        let p2 = self.pt_walker.next_table_mut(&mut p3[page.p2_index()])?;
+        let p3: &mut PageTable<Level3> = self.pt_walker.next_table_mut(&mut p4[page.p4_index()])?;
+        let p2: &mut PageTable<Level2> = self.pt_walker.next_table_mut(&mut p3[page.p3_index()])?;
 
         let entry = &mut p2[page.p2_index()];
         let flags = entry.flags();
@@ -244,8 +242,8 @@ impl<'a, P: PhysicalToVirtual> Mapper<Page2MB> for MappedPageTable<'a, P> {
 
     unsafe fn update_flags(&mut self, page: Page<Page2MB>, flags: PageTableFlags) -> Result<MapperFlush<Page2MB>, FlagUpdateError> {
         let p4 = &mut self.level_4_table;
-        let p3 = self.pt_walker.next_table_mut(&mut p4[page.p3_index()])?;
-        let p2 = self.pt_walker.next_table_mut(&mut p3[page.p2_index()])?;
+        let p3: &mut PageTable<Level3> = self.pt_walker.next_table_mut(&mut p4[page.p4_index()])?;
+        let p2: &mut PageTable<Level2> = self.pt_walker.next_table_mut(&mut p3[page.p3_index()])?;
 
         if p2[page.p2_index()].is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
@@ -258,8 +256,8 @@ impl<'a, P: PhysicalToVirtual> Mapper<Page2MB> for MappedPageTable<'a, P> {
 
     fn translate_page(&mut self, page: Page<Page2MB>) -> Result<Frame<Page2MB>, TranslateError> {
         let p4 = &mut self.level_4_table;
-        let p3 = self.pt_walker.next_table_mut(&mut p4[page.p3_index()])?;
-        let p2 = self.pt_walker.next_table_mut(&mut p3[page.p2_index()])?;
+        let p3: &mut PageTable<Level3> = self.pt_walker.next_table_mut(&mut p4[page.p4_index()])?;
+        let p2: &mut PageTable<Level2> = self.pt_walker.next_table_mut(&mut p3[page.p3_index()])?;
         let entry = &mut p2[page.p2_index()];
 
         if entry.is_unused() {
@@ -272,13 +270,13 @@ impl<'a, P: PhysicalToVirtual> Mapper<Page2MB> for MappedPageTable<'a, P> {
 }
 
 impl<'a, P: PhysicalToVirtual> Mapper<Page1GB> for MappedPageTable<'a, P> {
-    unsafe fn map_to<A>(&mut self, page: Page<Page1GB>, frame: UnusedFrame<Page1GB>, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush<Page1GB>, MapToError<Page1GB>> where A: FrameAllocator<Page4KB>, Self: Sized {
+    unsafe fn map_to<A>(&mut self, page: Page<Page1GB>, frame: Frame<Page1GB>, flags: PageTableFlags, allocator: &mut A) -> Result<MapperFlush<Page1GB>, MapToError<Page1GB>> where A: FrameAllocator<Page4KB>, Self: Sized {
         self.map_to_1g(page, frame, flags, allocator)
     }
 
     fn unmap(&mut self, page: Page<Page1GB>) -> Result<(Frame<Page1GB>, MapperFlush<Page1GB>), UnmapError> {
         let p4 = &mut self.level_4_table;
-        let p3 = self.pt_walker.next_table_mut(&mut p4[page.p3_index()])?;
+        let p3: &mut PageTable<Level3> = self.pt_walker.next_table_mut(&mut p4[page.p4_index()])?;
 
         let entry = &mut p3[page.p3_index()];
         let flags = entry.flags();
@@ -300,7 +298,7 @@ impl<'a, P: PhysicalToVirtual> Mapper<Page1GB> for MappedPageTable<'a, P> {
 
     unsafe fn update_flags(&mut self, page: Page<Page1GB>, flags: PageTableFlags) -> Result<MapperFlush<Page1GB>, FlagUpdateError> {
         let p4 = &mut self.level_4_table;
-        let p3 = self.pt_walker.next_table_mut(&mut p4[page.p3_index()])?;
+        let p3: &mut PageTable<Level3> = self.pt_walker.next_table_mut(&mut p4[page.p4_index()])?;
 
         if p3[page.p3_index()].is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
@@ -312,7 +310,7 @@ impl<'a, P: PhysicalToVirtual> Mapper<Page1GB> for MappedPageTable<'a, P> {
 
     fn translate_page(&mut self, page: Page<Page1GB>) -> Result<Frame<Page1GB>, TranslateError> {
         let p4 = &mut self.level_4_table;
-        let p3 = self.pt_walker.next_table_mut(&mut p4[page.p3_index()])?;
+        let p3: &mut PageTable<Level3> = self.pt_walker.next_table_mut(&mut p4[page.p4_index()])?;
         let entry = &mut p3[page.p3_index()];
 
         if entry.is_unused() {
@@ -326,33 +324,55 @@ impl<'a, P: PhysicalToVirtual> Mapper<Page1GB> for MappedPageTable<'a, P> {
 
 impl<'a, P: PhysicalToVirtual> MapAllSize for MappedPageTable<'a, P> {
     fn translate(&self, addr: VirtAddr) -> TranslationResult {
+        self.walk(addr, false)
+    }
+
+    fn translate_effective(&self, addr: VirtAddr) -> TranslationResult {
+        self.walk(addr, true)
+    }
+}
+
+impl<'a, P: PhysicalToVirtual> MappedPageTable<'a, P> {
+    /// `translate`和`translate_effective`的实现，`effective`为true时返回合并各级页表项后的访问权限
+    fn walk(&self, addr: VirtAddr, effective: bool) -> TranslationResult {
+        let leaf_flags = |parent: PageTableFlags, entry: &PageTableEntry| if effective {
+            effective_flags(parent, entry.flags())
+        } else {
+            entry.flags()
+        };
+
         let p4 = &self.level_4_table;
-        let p3 = match self.pt_walker.next_table(&p4[addr.page4_index()]) {
+        let p3: &PageTable<Level3> = match self.pt_walker.next_table(&p4[addr.page4_index()]) {
             Ok(pt) => pt,
             Err(PageTableWalkError::NotMapped) => return TranslationResult::PageNotMapped,
             Err(PageTableWalkError::MappedToHugePage) => panic!("level 4 entry has huge page bit set")
         };
-        let p2 = match self.pt_walker.next_table(&p3[addr.page3_index()]) {
+        let parent = effective_flags(ACCESS_FLAGS, p4[addr.page4_index()].flags());
+        let p2: &PageTable<Level2> = match self.pt_walker.next_table(&p3[addr.page3_index()]) {
             Ok(pt) => pt,
             Err(PageTableWalkError::NotMapped) => return TranslationResult::PageNotMapped,
             Err(PageTableWalkError::MappedToHugePage) => {
-                let frame = Frame::include_address(p3[addr.page3_index()].addr());
+                let entry = &p3[addr.page3_index()];
+                let frame = Frame::include_address(entry.addr());
                 let offset = addr.as_u64() & 0o_777_777_7777;
-                return TranslationResult::Frame1GB { frame, offset };
+                return TranslationResult::Frame1GB { frame, offset, flags: leaf_flags(parent, entry) };
             }
         };
-        let p1 = match self.pt_walker.next_table(&p2[addr.page2_index()]) {
+        let parent = effective_flags(parent, p3[addr.page3_index()].flags());
+        let p1: &PageTable<Level1> = match self.pt_walker.next_table(&p2[addr.page2_index()]) {
             Ok(pt) => pt,
             Err(PageTableWalkError::NotMapped) => return TranslationResult::PageNotMapped,
             Err(PageTableWalkError::MappedToHugePage) => {
-                let frame = Frame::include_address(p2[addr.page2_index()].addr());
-                let offset = addr.as_u64() & 0o_777_777_7777;
-                return TranslationResult::Frame1GB { frame, offset };
+                let entry = &p2[addr.page2_index()];
+                let frame = Frame::include_address(entry.addr());
+                let offset = addr.as_u64() & 0o_777_7777;
+                return TranslationResult::Frame2MB { frame, offset, flags: leaf_flags(parent, entry) };
             }
         };
+        let parent = effective_flags(parent, p2[addr.page2_index()].flags());
 
         let entry = &p1[addr.page1_index()];
-        if entry.is_unused() {
+        if !entry.flags().contains(PageTableFlags::PRESENT) {
             return TranslationResult::PageNotMapped;
         }
 
@@ -362,6 +382,6 @@ impl<'a, P: PhysicalToVirtual> MapAllSize for MappedPageTable<'a, P> {
         };
 
         let offset = u64::from(addr.page_offset());
-        TranslationResult::Frame4KB { frame, offset }
+        TranslationResult::Frame4KB { frame, offset, flags: leaf_flags(parent, entry) }
     }
-}
\ No newline at end of file
+}
@@ -1,15 +1,25 @@
+use alloc::vec::Vec;
+
+pub use inactive::InactivePageTableEditor;
+pub use map_pt::{MappedPageTable, PhysicalToVirtual};
 pub use page::RecursivePageTable;
+pub use pt_offset::{PageTableOffset, PhysOffset};
 
-use crate::arch::intel::instructions::page_table::flush;
-use crate::arch::intel::x64::address::{PhysAddr, PhysicalAddress, VirtAddr};
-use crate::arch::intel::x64::paging::{Frame, FrameAllocator, Page, Page1GB, Page2MB, Page4KB, PageSize};
+use crate::arch::intel::instructions::page_table::{flush, flush_all_global};
+use crate::arch::intel::x64::address::{align_down, PhysAddr, PhysicalAddress, VirtAddr, VirtualAddress};
+use crate::arch::intel::x64::paging::{Frame, FrameAllocator, Page, Page1GB, Page2MB, Page4KB, PageSize, PageTableEntry};
 use crate::arch::intel::x64::paging::flags::PageTableFlags;
-use crate::arch::intel::x64::paging::result::{FlagUpdateError, MapToError, TranslateError, TranslationResult, UnmapError};
+use crate::arch::intel::x64::paging::uaccess::USER_SPACE_END;
+use crate::arch::intel::x64::paging::result::{FlagUpdateError, MapToError, MergeError, PageTableWalkError, ProtectError, SplitError, TranslateError, TranslationResult, UnmapError};
 
-// mod map_pt;
-// mod pt_offset;
 // mod recursive_table;
 mod page;
+pub mod map_pt;
+pub mod pt_offset;
+pub mod inactive;
+pub mod scan;
+pub mod direct_map;
+pub mod audit;
 
 #[derive(Debug)]
 #[must_use = "Page Table changes must be flushed or ignored."]
@@ -29,6 +39,60 @@ impl<S: PageSize> MapperFlush<S> {
     pub fn ignore(self) {}
 }
 
+/// 批量刷新多个页面的TLB
+/// 页面数量超过`FLUSH_ALL_THRESHOLD`时直接刷新整个TLB，批次中可能含有全局页面，因此全局页面也会被刷新
+#[derive(Debug)]
+#[must_use = "Page Table changes must be flushed or ignored."]
+pub struct MapperFlushBatch {
+    pages: Vec<VirtAddr>,
+}
+
+impl MapperFlushBatch {
+    /// 超过该数量的页面时使用`flush_all_global`代替逐页`invlpg`
+    pub const FLUSH_ALL_THRESHOLD: usize = 64;
+
+    pub fn new() -> Self {
+        Self {
+            pages: Vec::new(),
+        }
+    }
+
+    /// 将单个页面的刷新合并到当前批次中
+    pub fn push<S: PageSize>(&mut self, flush: MapperFlush<S>) {
+        self.pages.push(flush.0.start_address());
+    }
+
+    /// 合并另一个批次
+    pub fn append(&mut self, mut other: MapperFlushBatch) {
+        self.pages.append(&mut other.pages);
+    }
+
+    /// 需要刷新的页面个数
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pages.is_empty()
+    }
+
+    pub fn flush(self) {
+        if self.pages.len() > Self::FLUSH_ALL_THRESHOLD {
+            unsafe {
+                flush_all_global();
+            }
+        } else {
+            for addr in self.pages {
+                unsafe {
+                    flush(addr);
+                }
+            }
+        }
+    }
+
+    pub fn ignore(self) {}
+}
+
 pub trait Mapper<S: PageSize> {
     /// 在页表中创建一个新的映射。
     /// 此函数需要其他物理帧才能创建新的页表。
@@ -62,14 +126,166 @@ pub trait MapAllSize: Mapper<Page4KB> + Mapper<Page1GB> + Mapper<Page2MB> {
     /// 如果给定的是有效虚拟地址，则返回映射的帧和该帧内的偏移量。 否则，将返回错误值。
     /// 此功能适用于各种种类的较大页面。
     fn translate(&self, addr: VirtAddr) -> TranslationResult;
+    /// 与`translate`相同，但返回的flags是CPU实际使用的访问权限：
+    /// 只有每一级页表项都设置了`WRITABLE`或`USER_ACCESSIBLE`时结果中才包含对应的标志位，
+    /// 任意一级页表项设置了`NO_EXECUTE`时结果中包含`NO_EXECUTE`
+    fn translate_effective(&self, addr: VirtAddr) -> TranslationResult;
     /// 将给定的虚拟地址转换为它映射到的物理地址。
     /// 如果给定地址没有有效的映射，则返回 None。
     fn translate_addr(&self, addr: VirtAddr) -> Option<PhysAddr> {
         match self.translate(addr) {
-            TranslationResult::Frame4KB { frame, offset } => Some(frame.start_address() + offset),
-            TranslationResult::Frame2MB { frame, offset } => Some(frame.start_address() + offset),
-            TranslationResult::Frame1GB { frame, offset } => Some(frame.start_address() + offset),
+            TranslationResult::Frame4KB { frame, offset, .. } => Some(frame.start_address() + offset),
+            TranslationResult::Frame2MB { frame, offset, .. } => Some(frame.start_address() + offset),
+            TranslationResult::Frame1GB { frame, offset, .. } => Some(frame.start_address() + offset),
             TranslationResult::PageNotMapped | TranslationResult::InvalidFrameAddress(_) => None,
         }
     }
-}
\ No newline at end of file
+}
+
+/// 需要每一级页表项都设置才生效的访问权限
+pub(crate) const ACCESS_FLAGS: PageTableFlags = PageTableFlags::from_bits_truncate(
+    PageTableFlags::WRITABLE.bits() | PageTableFlags::USER_ACCESSIBLE.bits()
+);
+
+/// 将上级页表项合并后的访问权限`parent`应用到页表项的`flags`上，遍历页表时从`ACCESS_FLAGS`开始逐级合并
+pub(crate) fn effective_flags(parent: PageTableFlags, flags: PageTableFlags) -> PageTableFlags {
+    (flags - (ACCESS_FLAGS - parent)) | (parent & PageTableFlags::NO_EXECUTE)
+}
+
+/// 直接访问4KB页面对应的1级页表项，用于保存交换项等不存在的页表项
+pub trait EntryAccess {
+    /// 返回给定页面的1级页表项
+    /// 如果上级页表没有映射返回`PageTableWalkError::NotMapped`，
+    /// 如果上级页表项是大页面返回`PageTableWalkError::MappedToHugePage`
+    fn entry_mut(&mut self, page: Page<Page4KB>) -> Result<&mut PageTableEntry, PageTableWalkError>;
+}
+
+/// `protect_range`会修改的页面属性，其他页面属性保持不变
+pub const PROTECTION_FLAGS: PageTableFlags = PageTableFlags::from_bits_truncate(
+    PageTableFlags::WRITABLE.bits() | PageTableFlags::USER_ACCESSIBLE.bits() | PageTableFlags::NO_EXECUTE.bits()
+);
+
+/// 大页面的拆分与合并
+pub trait HugePageMapper: MapAllSize {
+    /// 将1GB页面拆分为512个2MB页面，新的页面保留原来的页面属性并映射到连续的物理地址
+    /// 新的2级页表从`allocator`中分配
+    unsafe fn split_1gb<A>(&mut self, page: Page<Page1GB>, allocator: &mut A) -> Result<MapperFlush<Page1GB>, SplitError>
+        where A: FrameAllocator<Page4KB>, Self: Sized;
+
+    /// 将2MB页面拆分为512个4KB页面，新的页面保留原来的页面属性并映射到连续的物理地址
+    /// 新的1级页表从`allocator`中分配
+    unsafe fn split_2mb<A>(&mut self, page: Page<Page2MB>, allocator: &mut A) -> Result<MapperFlush<Page2MB>, SplitError>
+        where A: FrameAllocator<Page4KB>, Self: Sized;
+
+    /// 如果`page`对应的2级页表中512个2MB页面全部映射、物理地址连续且页面属性相同，将其合并为1GB页面
+    /// 合并后会立即刷新TLB，然后将2级页表的物理帧交还给`allocator`
+    unsafe fn merge_1gb<A>(&mut self, page: Page<Page1GB>, allocator: &mut A) -> Result<(), MergeError>
+        where A: FrameAllocator<Page4KB>, Self: Sized;
+
+    /// 如果`page`对应的1级页表中512个4KB页面全部映射、物理地址连续且页面属性相同，将其合并为2MB页面
+    /// 合并后会立即刷新TLB，然后将1级页表的物理帧交还给`allocator`
+    unsafe fn merge_2mb<A>(&mut self, page: Page<Page2MB>, allocator: &mut A) -> Result<(), MergeError>
+        where A: FrameAllocator<Page4KB>, Self: Sized;
+
+    /// 如果给定地址被1GB或2MB页面覆盖，将其逐级拆分直到该地址由4KB页面映射
+    /// 地址没有映射或已经由4KB页面映射时什么也不做
+    unsafe fn split_to_4kb<A>(&mut self, addr: VirtAddr, allocator: &mut A) -> Result<MapperFlushBatch, SplitError>
+        where A: FrameAllocator<Page4KB>, Self: Sized {
+        let mut batch = MapperFlushBatch::new();
+        loop {
+            match self.translate(addr) {
+                TranslationResult::Frame1GB { offset, .. } => {
+                    let page = Page::from_start_address(addr - offset).unwrap();
+                    batch.push(self.split_1gb(page, allocator)?);
+                }
+                TranslationResult::Frame2MB { offset, .. } => {
+                    let page = Page::from_start_address(addr - offset).unwrap();
+                    batch.push(self.split_2mb(page, allocator)?);
+                }
+                _ => return Ok(batch),
+            }
+        }
+    }
+
+    /// 修改`[start, start + len)`范围内所有页面的访问权限，类似于`mprotect`
+    /// `len`会向上对齐到4KB，`flags`中只有`PROTECTION_FLAGS`包含的标志位会被使用
+    ///
+    /// 范围可以由任意大小的页面混合映射，完全位于范围内的大页面会被直接修改，
+    /// 只有部分位于范围内的大页面会先被拆分。所有修改合并在返回的`MapperFlushBatch`中
+    ///
+    /// # Error
+    /// 范围超出地址空间或跨越非Canonical地址空洞时返回错误，此时不会修改任何页面。
+    /// 范围内存在没有映射的页面或拆分失败时返回错误，此时之前的修改已经生效并且已经刷新TLB
+    unsafe fn protect_range<A>(&mut self, start: VirtAddr, len: u64, flags: PageTableFlags, allocator: &mut A)
+                               -> Result<MapperFlushBatch, ProtectError>
+        where A: FrameAllocator<Page4KB>, Self: Sized {
+        if !start.is_aligned(Page4KB::P_SIZE) {
+            return Err(ProtectError::NotAligned(start));
+        }
+        let mut batch = MapperFlushBatch::new();
+        if len == 0 {
+            return Ok(batch);
+        }
+        let protection = flags & PROTECTION_FLAGS;
+        // 使用范围内的最后一个地址，范围可以一直延伸到地址空间的末尾
+        let last = len.checked_add(Page4KB::P_SIZE - 1)
+            .and_then(|len| start.as_u64().checked_add(align_down(len, Page4KB::P_SIZE) - 1))
+            .ok_or(ProtectError::AddressOverflow)?;
+        if start.as_u64() < USER_SPACE_END && last >= USER_SPACE_END {
+            return Err(ProtectError::NonCanonicalAddress(USER_SPACE_END));
+        }
+        let mut current = start.as_u64();
+
+        loop {
+            let addr = VirtAddr::new(current);
+            // 从`current`开始的对齐页面是否完全位于范围内
+            let covers = |size: u64| current + (size - 1) <= last;
+            let (size, result) = match self.translate(addr) {
+                TranslationResult::Frame1GB { offset, flags, .. } => {
+                    let page = Page::<Page1GB>::from_start_address(addr - offset).unwrap();
+                    if offset == 0 && covers(Page1GB::P_SIZE) {
+                        let result = <Self as Mapper<Page1GB>>::update_flags(self, page, flags - PROTECTION_FLAGS | protection)
+                            .map(|flush| batch.push(flush))
+                            .map_err(ProtectError::from);
+                        (Page1GB::P_SIZE, result)
+                    } else {
+                        (0, self.split_1gb(page, allocator).map(|flush| batch.push(flush)).map_err(ProtectError::from))
+                    }
+                }
+                TranslationResult::Frame2MB { offset, flags, .. } => {
+                    let page = Page::<Page2MB>::from_start_address(addr - offset).unwrap();
+                    if offset == 0 && covers(Page2MB::P_SIZE) {
+                        let result = <Self as Mapper<Page2MB>>::update_flags(self, page, flags - PROTECTION_FLAGS | protection)
+                            .map(|flush| batch.push(flush))
+                            .map_err(ProtectError::from);
+                        (Page2MB::P_SIZE, result)
+                    } else {
+                        (0, self.split_2mb(page, allocator).map(|flush| batch.push(flush)).map_err(ProtectError::from))
+                    }
+                }
+                TranslationResult::Frame4KB { flags, .. } => {
+                    let page = Page::<Page4KB>::from_start_address(addr).unwrap();
+                    let result = <Self as Mapper<Page4KB>>::update_flags(self, page, flags - PROTECTION_FLAGS | protection)
+                        .map(|flush| batch.push(flush))
+                        .map_err(ProtectError::from);
+                    (Page4KB::P_SIZE, result)
+                }
+                TranslationResult::PageNotMapped | TranslationResult::InvalidFrameAddress(_) => {
+                    (0, Err(ProtectError::PageNotMapped(addr)))
+                }
+            };
+            if let Err(err) = result {
+                batch.flush();
+                return Err(err);
+            }
+            // 拆分大页面后`size`为0，重新处理同一个地址
+            if size == 0 {
+                continue;
+            }
+            match current.checked_add(size) {
+                Some(next) if next <= last => current = next,
+                _ => return Ok(batch),
+            }
+        }
+    }
+}
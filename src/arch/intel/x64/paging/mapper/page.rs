@@ -1,14 +1,34 @@
+use core::fmt;
+
+use bit_field::BitField;
+
 use crate::arch::intel::chips::control::CR3;
-use crate::arch::intel::x64::address::{VirtAddr, VirtualAddress};
-use crate::arch::intel::x64::paging::{Frame, FrameAllocator, NotGiantPageSize, Page, Page1GB, Page2MB, Page4KB, PageIndex, PageSize, PageTable, PageTableEntry, UnusedFrame};
+use crate::arch::intel::instructions::page_table::flush;
+use crate::arch::intel::x64::address::{align_down, PhysAddr, PhysicalAddress, VirtAddr, VirtualAddress};
+use crate::arch::intel::x64::paging::{ENTRY_COUNT, Frame, FrameAllocator, Level1, Level2, Level3, NotGiantPageSize, Page, Page1GB, Page2MB, Page4KB, PageIndex, PageSize, PageTable, PageTableEntry, TableLevel, UnusedFrame};
 use crate::arch::intel::x64::paging::flags::PageTableFlags;
-use crate::arch::intel::x64::paging::mapper::{MapAllSize, Mapper, MapperFlush};
-use crate::arch::intel::x64::paging::result::{FlagUpdateError, FrameError, MapToError, TranslateError, TranslationResult, UnmapError};
+use crate::arch::intel::x64::paging::mapper::{ACCESS_FLAGS, effective_flags, EntryAccess, HugePageMapper, MapAllSize, Mapper, MapperFlush};
+use crate::arch::intel::x64::paging::result::{FlagUpdateError, FrameError, MapToError, MergeError, PageTableWalkError, SplitError, TranslateError, TranslationResult, UnmapError};
 
-#[derive(Debug)]
+/// 使用递归映射访问的页表
+///
+/// **注意**：默认情况下`Mapper<Page4KB>::update_flags`和`unmap`不会拆分覆盖目标页面的2MB或1GB页面，
+/// 而是返回`ParentEntryHugePage`。需要透明拆分时必须先调用`set_split_allocator`提供分配新页表的分配器
 pub struct RecursivePageTable<'a> {
-    p4: &'a mut PageTable,
-    recursive_index: PageIndex,
+    pub(super) p4: &'a mut PageTable,
+    pub(super) recursive_index: PageIndex,
+    /// 拆分大页面时用于分配新页表的分配器
+    split_allocator: Option<&'a mut dyn FrameAllocator<Page4KB>>,
+}
+
+impl<'a> fmt::Debug for RecursivePageTable<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RecursivePageTable")
+            .field("p4", &self.p4)
+            .field("recursive_index", &self.recursive_index)
+            .field("split_allocator", &self.split_allocator.is_some())
+            .finish()
+    }
 }
 
 impl<'a> RecursivePageTable<'a> {
@@ -42,6 +62,7 @@ impl<'a> RecursivePageTable<'a> {
         Ok(RecursivePageTable {
             p4: table,
             recursive_index,
+            split_allocator: None,
         })
     }
 
@@ -55,36 +76,60 @@ impl<'a> RecursivePageTable<'a> {
         RecursivePageTable {
             p4: table,
             recursive_index,
+            split_allocator: None,
         }
     }
 
+    /// 设置拆分大页面时使用的物理帧分配器
+    /// 设置后对被2MB或1GB页面覆盖的4KB页面调用`Mapper<Page4KB>::update_flags`或`unmap`时，
+    /// 大页面会先被拆分为4KB页面，新的页表从`allocator`中分配，否则返回`ParentEntryHugePage`
+    pub fn set_split_allocator(&mut self, allocator: &'a mut dyn FrameAllocator<Page4KB>) {
+        self.split_allocator = Some(allocator);
+    }
+
+    /// 取回拆分大页面时使用的物理帧分配器
+    pub fn take_split_allocator(&mut self) -> Option<&'a mut dyn FrameAllocator<Page4KB>> {
+        self.split_allocator.take()
+    }
+
+    /// 如果设置了拆分分配器并且`page`被大页面覆盖，将大页面拆分到4KB并立即刷新TLB
+    unsafe fn split_covering_huge_page(&mut self, page: Page<Page4KB>) -> Result<(), SplitError> {
+        let mut allocator = match self.split_allocator.take() {
+            Some(allocator) => allocator,
+            None => return Ok(()),
+        };
+        let result = self.split_to_4kb(page.start_address(), &mut allocator);
+        self.split_allocator = Some(allocator);
+        result.map(|batch| batch.flush())
+    }
+
     /// Internal helper function to create the page table of the next level if needed.
     ///
     /// If the passed entry is unused, a new frame is allocated from the given allocator, zeroed,
     /// and the entry is updated to that address. If the passed entry is already mapped, the next
     /// table is returned directly.
     ///
-    /// The `next_page_table` page must be the page of the next page table in the hierarchy.
+    /// The `next_table` pointer must be the recursive address of the next page table in the hierarchy.
     ///
     /// Returns `MapToError::FrameAllocationFailed` if the entry is unused and the allocator
     /// returned `None`. Returns `MapToError::ParentEntryHugePage` if the `HUGE_PAGE` flag is set
     /// in the passed entry.
-    unsafe fn create_next_table<'b, A, S: PageSize>(
+    unsafe fn create_next_table<'b, A, S: PageSize, L: TableLevel>(
         entry: &'b mut PageTableEntry,
-        next_table_page: Page,
+        next_table: *mut PageTable<L>,
         allocator: &mut A,
-    ) -> Result<&'b mut PageTable, MapToError<S>>
+    ) -> Result<&'b mut PageTable<L>, MapToError<S>>
         where
             A: FrameAllocator<Page4KB>,
     {
         /// This inner function is used to limit the scope of `unsafe`.
         ///
         /// This is a safe function, so we need to use `unsafe` blocks when we do something unsafe.
-        fn inner<'b, A, S: PageSize>(
+        fn inner<'b, A, S: PageSize, L: TableLevel>(
             entry: &'b mut PageTableEntry,
-            next_table_page: Page,
+            next_table: *mut PageTable<L>,
             allocator: &mut A,
-        ) -> Result<&'b mut PageTable, MapToError<S>>
+        ) -> Result<&'b mut PageTable<L>, MapToError<S>>
             where
                 A: FrameAllocator<Page4KB>,
         {
@@ -106,15 +151,36 @@ impl<'a> RecursivePageTable<'a> {
                 return Err(MapToError::ParentEntryHugePage);
             }
 
-            let page_table_ptr = next_table_page.start_address().as_mut_ptr();
-            let page_table: &mut PageTable = unsafe { &mut *(page_table_ptr) };
+            let page_table: &mut PageTable<L> = unsafe { &mut *(next_table) };
             if created {
                 page_table.zero();
             }
             Ok(page_table)
         }
 
-        inner(entry, next_table_page, allocator)
+        inner(entry, next_table, allocator)
+    }
+
+    /// 通过4级页表中的空闲项临时访问给定的物理帧，在`f`中将其作为页表进行初始化
+    ///
+    /// 空闲项指向该物理帧后，物理帧可以通过递归地址`0o_rrr_rrr_rrr_iii_0000`访问，
+    /// `f`返回后空闲项会被清除。如果4级页表中没有空闲项则返回None
+    unsafe fn with_scratch_table<L, F, R>(&mut self, frame: Frame, f: F) -> Option<R>
+        where L: TableLevel, F: FnOnce(&mut PageTable<L>) -> R {
+        let recursive_index = self.recursive_index;
+        let index = (0..ENTRY_COUNT as u16).rev()
+            .map(PageIndex::new)
+            .find(|index| *index != recursive_index && self.p4[*index].is_unused())?;
+
+        self.p4[index].set_frame(frame, PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+        let page = Page::from_page_table_indices(recursive_index, recursive_index, recursive_index, index);
+        flush(page.start_address());
+
+        let ret = f(&mut *(page.start_address().as_mut_ptr::<PageTable<L>>()));
+
+        self.p4[index].set_unused();
+        flush(page.start_address());
+        Some(ret)
     }
 
     /// Helper function for implementing Mapper. Safe to limit the scope of unsafe, see
@@ -133,8 +199,7 @@ impl<'a> RecursivePageTable<'a> {
 
         let p4 = &mut self.p4;
 
-        let p3_page = p3_page(page, self.recursive_index);
-        let p3 = unsafe { Self::create_next_table(&mut p4[page.p4_index()], p3_page, allocator)? };
+        let p3 = unsafe { Self::create_next_table(&mut p4[page.p4_index()], p3_ptr(page, self.recursive_index), allocator)? };
 
         if !p3[page.p3_index()].is_unused() {
             return Err(MapToError::PageAlreadyMapped(unsafe { UnusedFrame::new(frame) }));
@@ -160,11 +225,9 @@ impl<'a> RecursivePageTable<'a> {
 
         let p4 = &mut self.p4;
 
-        let p3_page = p3_page(page, self.recursive_index);
-        let p3 = unsafe { Self::create_next_table(&mut p4[page.p4_index()], p3_page, allocator)? };
+        let p3 = unsafe { Self::create_next_table(&mut p4[page.p4_index()], p3_ptr(page, self.recursive_index), allocator)? };
 
-        let p2_page = p2_page(page, self.recursive_index);
-        let p2 = unsafe { Self::create_next_table(&mut p3[page.p3_index()], p2_page, allocator)? };
+        let p2 = unsafe { Self::create_next_table(&mut p3[page.p3_index()], p2_ptr(page, self.recursive_index), allocator)? };
 
         if !p2[page.p2_index()].is_unused() {
             return Err(MapToError::PageAlreadyMapped(unsafe { UnusedFrame::new(frame) }));
@@ -188,14 +251,11 @@ impl<'a> RecursivePageTable<'a> {
     {
         let p4 = &mut self.p4;
 
-        let p3_page = p3_page(page, self.recursive_index);
-        let p3 = unsafe { Self::create_next_table(&mut p4[page.p4_index()], p3_page, allocator)? };
+        let p3 = unsafe { Self::create_next_table(&mut p4[page.p4_index()], p3_ptr(page, self.recursive_index), allocator)? };
 
-        let p2_page = p2_page(page, self.recursive_index);
-        let p2 = unsafe { Self::create_next_table(&mut p3[page.p3_index()], p2_page, allocator)? };
+        let p2 = unsafe { Self::create_next_table(&mut p3[page.p3_index()], p2_ptr(page, self.recursive_index), allocator)? };
 
-        let p1_page = p1_page(page, self.recursive_index);
-        let p1 = unsafe { Self::create_next_table(&mut p2[page.p2_index()], p1_page, allocator)? };
+        let p1 = unsafe { Self::create_next_table(&mut p2[page.p2_index()], p1_ptr(page, self.recursive_index), allocator)? };
 
         if !p1[page.p1_index()].is_unused() {
             return Err(MapToError::PageAlreadyMapped(unsafe { UnusedFrame::new(frame) }));
@@ -365,12 +425,19 @@ impl<'a> Mapper<Page2MB> for RecursivePageTable<'a> {
         if p3[page.p3_index()].is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
         }
+        if p3[page.p3_index()].flags().contains(Flags::HUGE_PAGE) {
+            return Err(FlagUpdateError::ParentEntryHugePage);
+        }
 
         let p2 = unsafe { &mut *(p2_ptr(page, self.recursive_index)) };
 
         if p2[page.p2_index()].is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
         }
+        // 2级页表项指向的是1级页表而不是2MB页面
+        if !p2[page.p2_index()].flags().contains(Flags::HUGE_PAGE) {
+            return Err(FlagUpdateError::ParentEntryHugePage);
+        }
 
         p2[page.p2_index()].set_flags(flags | Flags::HUGE_PAGE);
 
@@ -403,6 +470,8 @@ impl<'a> Mapper<Page2MB> for RecursivePageTable<'a> {
     }
 }
 
+/// 只有通过`set_split_allocator`设置了分配器时，`update_flags`和`unmap`才会先拆分覆盖目标页面的大页面，
+/// 没有设置分配器时遇到大页面返回`ParentEntryHugePage`
 impl<'a> Mapper<Page4KB> for RecursivePageTable<'a> {
     #[inline]
     unsafe fn map_to<A>(
@@ -418,10 +487,13 @@ impl<'a> Mapper<Page4KB> for RecursivePageTable<'a> {
         self.map_to_4kib(page, frame, flags, allocator)
     }
 
+    /// 页面被大页面覆盖时，设置了`set_split_allocator`则先拆分大页面，否则返回`UnmapError::ParentEntryHugePage`
     fn unmap(
         &mut self,
         page: Page<Page4KB>,
     ) -> Result<(Frame<Page4KB>, MapperFlush<Page4KB>), UnmapError> {
+        // 拆分不改变任何地址的映射关系
+        unsafe { self.split_covering_huge_page(page)? };
         let p4 = &mut self.p4;
         let p4_entry = &p4[page.p4_index()];
         p4_entry.frame().map_err(|err| match err {
@@ -446,15 +518,17 @@ impl<'a> Mapper<Page4KB> for RecursivePageTable<'a> {
         let p1 = unsafe { &mut *(p1_ptr(page.clone(), self.recursive_index)) };
         let p1_entry = &mut p1[page.p1_index()];
 
-        let frame = p1_entry.frame().map_err(|err| match err {
-            FrameError::FrameNotPresent => UnmapError::PageNotMapped,
-            FrameError::HugeFrame => UnmapError::ParentEntryHugePage,
-        })?;
+        // 1级页表项的第7位表示PAT，不能使用会把该位当作大页面的`frame`
+        if !p1_entry.flags().contains(PageTableFlags::PRESENT) {
+            return Err(UnmapError::PageNotMapped);
+        }
+        let frame = Frame::include_address(p1_entry.addr());
 
         p1_entry.set_unused();
         Ok((frame, MapperFlush::new(page)))
     }
 
+    /// 页面被大页面覆盖时，设置了`set_split_allocator`则先拆分大页面，否则返回`FlagUpdateError::ParentEntryHugePage`
     // allow unused_unsafe until https://github.com/rust-lang/rfcs/pull/2585 lands
     #[allow(unused_unsafe)]
     unsafe fn update_flags(
@@ -462,6 +536,7 @@ impl<'a> Mapper<Page4KB> for RecursivePageTable<'a> {
         page: Page<Page4KB>,
         flags: PageTableFlags,
     ) -> Result<MapperFlush<Page4KB>, FlagUpdateError> {
+        self.split_covering_huge_page(page)?;
         let p4 = &mut self.p4;
 
         if p4[page.p4_index()].is_unused() {
@@ -473,12 +548,18 @@ impl<'a> Mapper<Page4KB> for RecursivePageTable<'a> {
         if p3[page.p3_index()].is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
         }
+        if p3[page.p3_index()].flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(FlagUpdateError::ParentEntryHugePage);
+        }
 
         let p2 = unsafe { &mut *(p2_ptr(page.clone(), self.recursive_index)) };
 
         if p2[page.p2_index()].is_unused() {
             return Err(FlagUpdateError::PageNotMapped);
         }
+        if p2[page.p2_index()].flags().contains(PageTableFlags::HUGE_PAGE) {
+            return Err(FlagUpdateError::ParentEntryHugePage);
+        }
 
         let p1 = unsafe { &mut *(p1_ptr(page.clone(), self.recursive_index)) };
 
@@ -525,9 +606,25 @@ impl<'a> Mapper<Page4KB> for RecursivePageTable<'a> {
 }
 
 impl<'a> MapAllSize for RecursivePageTable<'a> {
-    #[allow(clippy::inconsistent_digit_grouping)]
     fn translate(&self, addr: VirtAddr) -> TranslationResult {
+        self.walk(addr, false)
+    }
+
+    fn translate_effective(&self, addr: VirtAddr) -> TranslationResult {
+        self.walk(addr, true)
+    }
+}
+
+impl<'a> RecursivePageTable<'a> {
+    /// `translate`和`translate_effective`的实现，`effective`为true时返回合并各级页表项后的访问权限
+    #[allow(clippy::inconsistent_digit_grouping)]
+    fn walk(&self, addr: VirtAddr, effective: bool) -> TranslationResult {
         let page = Page::include_address(addr);
+        let leaf_flags = |parent: PageTableFlags, entry: &PageTableEntry| if effective {
+            effective_flags(parent, entry.flags())
+        } else {
+            entry.flags()
+        };
 
         let p4 = &self.p4;
         let p4_entry = &p4[addr.page4_index()];
@@ -537,6 +634,7 @@ impl<'a> MapAllSize for RecursivePageTable<'a> {
         if p4_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
             panic!("level 4 entry has huge page bit set")
         }
+        let parent = effective_flags(ACCESS_FLAGS, p4_entry.flags());
 
         let p3 = unsafe { &*(p3_ptr(page.clone(), self.recursive_index)) };
         let p3_entry = &p3[addr.page3_index()];
@@ -546,8 +644,10 @@ impl<'a> MapAllSize for RecursivePageTable<'a> {
         if p3_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
             let frame = Frame::include_address(p3[addr.page3_index()].addr());
             let offset = addr.as_u64() & 0o_777_777_7777;
-            return TranslationResult::Frame1GB { frame, offset };
+            let flags = leaf_flags(parent, p3_entry);
+            return TranslationResult::Frame1GB { frame, offset, flags };
         }
+        let parent = effective_flags(parent, p3_entry.flags());
 
         let p2 = unsafe { &*(p2_ptr(page.clone(), self.recursive_index)) };
         let p2_entry = &p2[addr.page2_index()];
@@ -557,25 +657,227 @@ impl<'a> MapAllSize for RecursivePageTable<'a> {
         if p2_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
             let frame = Frame::include_address(p2[addr.page2_index()].addr());
             let offset = addr.as_u64() & 0o_777_7777;
-            return TranslationResult::Frame4KB { frame, offset };
+            let flags = leaf_flags(parent, p2_entry);
+            return TranslationResult::Frame2MB { frame, offset, flags };
         }
+        let parent = effective_flags(parent, p2_entry.flags());
 
         let p1 = unsafe { &*(p1_ptr(page, self.recursive_index)) };
         let p1_entry = &p1[addr.page1_index()];
-        if p1_entry.is_unused() {
+        // 不存在的页表项可能保存着交换项，同样视为没有映射
+        if !p1_entry.flags().contains(PageTableFlags::PRESENT) {
             return TranslationResult::PageNotMapped;
         }
-        if p1_entry.flags().contains(PageTableFlags::HUGE_PAGE) {
-            panic!("level 1 entry has huge page bit set")
-        }
+        // 1级页表项的第7位表示PAT，与大页面无关
         let frame = Frame::include_address(p1_entry.addr());
         let offset = u64::from(addr.page_offset());
-        TranslationResult::Frame4KB { frame, offset }
+        let flags = leaf_flags(parent, p1_entry);
+        TranslationResult::Frame4KB { frame, offset, flags }
+    }
+}
+
+impl<'a> EntryAccess for RecursivePageTable<'a> {
+    fn entry_mut(&mut self, page: Page<Page4KB>) -> Result<&mut PageTableEntry, PageTableWalkError> {
+        self.p4[page.p4_index()].frame()?;
+
+        let p3 = unsafe { &*(p3_ptr(page, self.recursive_index)) };
+        p3[page.p3_index()].frame()?;
+
+        let p2 = unsafe { &*(p2_ptr(page, self.recursive_index)) };
+        p2[page.p2_index()].frame()?;
+
+        let p1 = unsafe { &mut *(p1_ptr(page, self.recursive_index)) };
+        Ok(&mut p1[page.p1_index()])
+    }
+}
+
+impl<'a> HugePageMapper for RecursivePageTable<'a> {
+    unsafe fn split_1gb<A>(&mut self, page: Page<Page1GB>, allocator: &mut A) -> Result<MapperFlush<Page1GB>, SplitError>
+        where A: FrameAllocator<Page4KB> {
+        if self.p4[page.p4_index()].is_unused() {
+            return Err(SplitError::PageNotMapped);
+        }
+        let p3 = &mut *(p3_ptr(page, self.recursive_index));
+        let entry = p3[page.p3_index()];
+        check_huge_entry(&entry)?;
+
+        let flags = entry.flags();
+        let (base, pat) = huge_entry_addr(&entry, Page1GB::P_SIZE);
+        let table_frame = allocator.alloc().ok_or(SplitError::FrameAllocateFailed)?;
+        let filled = self.with_scratch_table(table_frame.frame(), |table: &mut PageTable<Level2>| {
+            for (i, e) in table.iter_mut().enumerate() {
+                let mut addr = base + i as u64 * Page2MB::P_SIZE;
+                addr.set_bit(12, pat);
+                e.set_addr(PhysAddr::new(addr), flags);
+            }
+        });
+        if filled.is_none() {
+            allocator.dealloc(table_frame);
+            return Err(SplitError::NoFreeEntry);
+        }
+        p3[page.p3_index()].set_frame(table_frame.frame(), table_entry_flags());
+        // 新的2级页表的递归地址之前可能缓存着旧的映射
+        let sub_page = Page::<Page2MB>::from_start_address(page.start_address()).unwrap();
+        flush(p2_page(sub_page, self.recursive_index).start_address());
+        Ok(MapperFlush::new(page))
+    }
+
+    unsafe fn split_2mb<A>(&mut self, page: Page<Page2MB>, allocator: &mut A) -> Result<MapperFlush<Page2MB>, SplitError>
+        where A: FrameAllocator<Page4KB> {
+        if self.p4[page.p4_index()].is_unused() {
+            return Err(SplitError::PageNotMapped);
+        }
+        let p3 = &*(p3_ptr(page, self.recursive_index));
+        p3[page.p3_index()].frame().map_err(|err| match err {
+            FrameError::FrameNotPresent => SplitError::PageNotMapped,
+            FrameError::HugeFrame => SplitError::NotHugePage,
+        })?;
+        let p2 = &mut *(p2_ptr(page, self.recursive_index));
+        let entry = p2[page.p2_index()];
+        check_huge_entry(&entry)?;
+
+        // 4KB页表项中第7位表示PAT，而大页面的PAT位于第12位
+        let (base, pat) = huge_entry_addr(&entry, Page2MB::P_SIZE);
+        let mut flags = entry.flags() - PageTableFlags::HUGE_PAGE;
+        flags.set(PageTableFlags::HUGE_PAGE, pat);
+        let table_frame = allocator.alloc().ok_or(SplitError::FrameAllocateFailed)?;
+        let filled = self.with_scratch_table(table_frame.frame(), |table: &mut PageTable<Level1>| {
+            for (i, e) in table.iter_mut().enumerate() {
+                e.set_addr(PhysAddr::new(base + i as u64 * Page4KB::P_SIZE), flags);
+            }
+        });
+        if filled.is_none() {
+            allocator.dealloc(table_frame);
+            return Err(SplitError::NoFreeEntry);
+        }
+        p2[page.p2_index()].set_frame(table_frame.frame(), table_entry_flags());
+        // 新的1级页表的递归地址之前可能缓存着旧的映射
+        let sub_page = Page::<Page4KB>::from_start_address(page.start_address()).unwrap();
+        flush(p1_page(sub_page, self.recursive_index).start_address());
+        // 拆分后的4KB页面必须映射到原来的物理地址，并且PAT等页面属性保持不变
+        debug_assert!(match self.translate(page.start_address()) {
+            TranslationResult::Frame4KB { frame, flags: translated, .. } => frame.start_address().as_u64() == base && translated == flags,
+            _ => false,
+        });
+        Ok(MapperFlush::new(page))
+    }
+
+    unsafe fn merge_1gb<A>(&mut self, page: Page<Page1GB>, allocator: &mut A) -> Result<(), MergeError>
+        where A: FrameAllocator<Page4KB> {
+        if self.p4[page.p4_index()].is_unused() {
+            return Err(MergeError::PageNotMapped);
+        }
+        let p3 = &mut *(p3_ptr(page, self.recursive_index));
+        let table_frame = p3[page.p3_index()].frame().map_err(|err| match err {
+            FrameError::FrameNotPresent => MergeError::PageNotMapped,
+            FrameError::HugeFrame => MergeError::AlreadyHugePage,
+        })?;
+
+        let sub_page = Page::<Page2MB>::from_start_address(page.start_address()).unwrap();
+        let p2 = &*(p2_ptr(sub_page, self.recursive_index));
+        let first = p2[0];
+        if !first.flags().contains(PageTableFlags::PRESENT | PageTableFlags::HUGE_PAGE) {
+            return Err(MergeError::NotMergeable);
+        }
+        let (base, pat) = huge_entry_addr(&first, Page2MB::P_SIZE);
+        if base % Page1GB::P_SIZE != 0 {
+            return Err(MergeError::NotMergeable);
+        }
+        let flags = merge_flags(p2, |i| {
+            let mut addr = base + i as u64 * Page2MB::P_SIZE;
+            addr.set_bit(12, pat);
+            addr
+        })?;
+
+        p3[page.p3_index()].set_addr(first.addr(), flags | PageTableFlags::HUGE_PAGE);
+        flush(page.start_address());
+        flush(p2_page(sub_page, self.recursive_index).start_address());
+        allocator.dealloc(UnusedFrame::new(table_frame));
+        Ok(())
+    }
+
+    unsafe fn merge_2mb<A>(&mut self, page: Page<Page2MB>, allocator: &mut A) -> Result<(), MergeError>
+        where A: FrameAllocator<Page4KB> {
+        if self.p4[page.p4_index()].is_unused() {
+            return Err(MergeError::PageNotMapped);
+        }
+        let p3 = &*(p3_ptr(page, self.recursive_index));
+        p3[page.p3_index()].frame().map_err(|err| match err {
+            FrameError::FrameNotPresent => MergeError::PageNotMapped,
+            FrameError::HugeFrame => MergeError::AlreadyHugePage,
+        })?;
+        let p2 = &mut *(p2_ptr(page, self.recursive_index));
+        let table_frame = p2[page.p2_index()].frame().map_err(|err| match err {
+            FrameError::FrameNotPresent => MergeError::PageNotMapped,
+            FrameError::HugeFrame => MergeError::AlreadyHugePage,
+        })?;
+
+        let sub_page = Page::<Page4KB>::from_start_address(page.start_address()).unwrap();
+        let p1 = &*(p1_ptr(sub_page, self.recursive_index));
+        let base = p1[0].addr().as_u64();
+        if base % Page2MB::P_SIZE != 0 {
+            return Err(MergeError::NotMergeable);
+        }
+        let mut flags = merge_flags(p1, |i| base + i as u64 * Page4KB::P_SIZE)?;
+
+        // 4KB页表项中第7位表示PAT，合并后需要移动到第12位
+        let mut addr = base;
+        addr.set_bit(12, flags.contains(PageTableFlags::HUGE_PAGE));
+        flags.remove(PageTableFlags::HUGE_PAGE);
+        p2[page.p2_index()].set_addr(PhysAddr::new(addr), flags | PageTableFlags::HUGE_PAGE);
+        flush(page.start_address());
+        flush(p1_page(sub_page, self.recursive_index).start_address());
+        allocator.dealloc(UnusedFrame::new(table_frame));
+        Ok(())
+    }
+}
+
+/// 检查页表项是否映射了大页面
+fn check_huge_entry(entry: &PageTableEntry) -> Result<(), SplitError> {
+    let flags = entry.flags();
+    if !flags.contains(PageTableFlags::PRESENT) {
+        return Err(SplitError::PageNotMapped);
+    }
+    if !flags.contains(PageTableFlags::HUGE_PAGE) {
+        return Err(SplitError::NotHugePage);
+    }
+    Ok(())
+}
+
+/// 返回大页面页表项映射的物理地址以及第12位的PAT标志
+fn huge_entry_addr(entry: &PageTableEntry, size: u64) -> (u64, bool) {
+    let addr = entry.addr().as_u64();
+    (align_down(addr, size), addr.get_bit(12))
+}
+
+/// 指向下级页表的页表项属性，不限制任何访问，页面的访问权限完全由映射页面的页表项控制，
+/// 之后修改单个页面的访问权限时不需要再修改上级页表项
+fn table_entry_flags() -> PageTableFlags {
+    PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE
+}
+
+/// 检查页表中所有页表项都已映射到`expected(i)`给出的物理地址并且页面属性相同
+/// 返回合并后的页面属性，`ACCESSED`和`DIRTY`标志位取所有页表项的并集
+fn merge_flags<L, F>(table: &PageTable<L>, expected: F) -> Result<PageTableFlags, MergeError>
+    where L: TableLevel, F: Fn(usize) -> u64 {
+    let ignored = PageTableFlags::ACCESSED | PageTableFlags::DIRTY;
+    let first = table[0].flags();
+    if !first.contains(PageTableFlags::PRESENT) {
+        return Err(MergeError::NotMergeable);
+    }
+    let mut flags = first;
+    for (i, entry) in table.iter().enumerate() {
+        let entry_flags = entry.flags();
+        if entry.addr().as_u64() != expected(i) || entry_flags - ignored != first - ignored {
+            return Err(MergeError::NotMergeable);
+        }
+        flags |= entry_flags & ignored;
     }
+    Ok(flags)
 }
 
 #[inline]
-fn p3_ptr<S: PageSize>(page: Page<S>, recursive_index: PageIndex) -> *mut PageTable {
+fn p3_ptr<S: PageSize>(page: Page<S>, recursive_index: PageIndex) -> *mut PageTable<Level3> {
     p3_page(page, recursive_index).start_address().as_mut_ptr()
 }
 
@@ -590,7 +892,7 @@ fn p3_page<S: PageSize>(page: Page<S>, recursive_index: PageIndex) -> Page {
 }
 
 #[inline]
-fn p2_ptr<S: NotGiantPageSize>(page: Page<S>, recursive_index: PageIndex) -> *mut PageTable {
+fn p2_ptr<S: NotGiantPageSize>(page: Page<S>, recursive_index: PageIndex) -> *mut PageTable<Level2> {
     p2_page(page, recursive_index).start_address().as_mut_ptr()
 }
 
@@ -605,7 +907,7 @@ fn p2_page<S: NotGiantPageSize>(page: Page<S>, recursive_index: PageIndex) -> Pa
 }
 
 #[inline]
-fn p1_ptr(page: Page<Page4KB>, recursive_index: PageIndex) -> *mut PageTable {
+fn p1_ptr(page: Page<Page4KB>, recursive_index: PageIndex) -> *mut PageTable<Level1> {
     p1_page(page, recursive_index).start_address().as_mut_ptr()
 }
 
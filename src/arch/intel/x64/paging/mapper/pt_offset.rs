@@ -1,18 +1,33 @@
-use crate::bits::PageTableFlags;
-use crate::ia_32e::paging::allocator::{FrameAllocator, UnusedFrame};
-use crate::ia_32e::paging::frame::Frame;
-use crate::ia_32e::paging::mapper::{MapAllSize, Mapper, MapperFlush};
-use crate::ia_32e::paging::mapper::map_pt::{MappedPageTable, PhysicalToVirtual};
-use crate::ia_32e::paging::page::{Page, Page1GB, Page2MB, Page4KB};
-use crate::ia_32e::paging::PageTable;
-use crate::ia_32e::paging::result::{FlagUpdateError, MapToError, TranslateError, TranslationResult, UnmapError};
-use crate::ia_32e::VirtAddr;
-
-#[derive(Debug)]
+use crate::arch::intel::x64::address::{PhysAddr, PhysicalAddress, VirtAddr, VirtualAddress};
+use crate::arch::intel::x64::paging::{Frame, FrameAllocator, Page, Page1GB, Page2MB, Page4KB, PageTable};
+use crate::arch::intel::x64::paging::flags::PageTableFlags;
+use crate::arch::intel::x64::paging::mapper::{MapAllSize, Mapper, MapperFlush};
+use crate::arch::intel::x64::paging::mapper::map_pt::{MappedPageTable, PhysicalToVirtual};
+use crate::arch::intel::x64::paging::result::{FlagUpdateError, MapToError, TranslateError, TranslationResult, UnmapError};
+
+/// 物理内存被完整映射到`offset`开始的虚拟地址，物理地址`paddr`位于`offset + paddr`
+#[derive(Debug, Copy, Clone)]
 pub struct PhysOffset {
     offset: VirtAddr,
 }
 
+impl PhysOffset {
+    /// 使用直接映射的起始虚拟地址创建
+    pub fn new(offset: VirtAddr) -> Self {
+        Self { offset }
+    }
+
+    /// 直接映射的起始虚拟地址
+    pub fn offset(&self) -> VirtAddr {
+        self.offset
+    }
+
+    /// 将物理地址转换为直接映射中的虚拟地址
+    pub fn phys_to_virt(&self, phys: PhysAddr) -> VirtAddr {
+        self.offset + phys.as_u64()
+    }
+}
+
 impl PhysicalToVirtual for PhysOffset {
     fn phy_to_vir(&self, phy_frame: Frame<Page4KB>) -> *mut PageTable {
         let phy = phy_frame.start_address().as_u64();
@@ -21,6 +36,7 @@ impl PhysicalToVirtual for PhysOffset {
     }
 }
 
+/// 通过物理内存直接映射访问页表的`Mapper`
 #[derive(Debug)]
 pub struct PageTableOffset<'a> {
     inner: MappedPageTable<'a, PhysOffset>
@@ -28,7 +44,7 @@ pub struct PageTableOffset<'a> {
 
 impl<'a> PageTableOffset<'a> {
     pub unsafe fn new(level_4_page_table: &'a mut PageTable, virt_offset: VirtAddr) -> Self {
-        let offset = PhysOffset { offset: virt_offset };
+        let offset = PhysOffset::new(virt_offset);
         Self {
             inner: MappedPageTable::new(level_4_page_table, offset)
         }
@@ -93,4 +109,8 @@ impl<'a> MapAllSize for PageTableOffset<'a> {
     fn translate(&self, addr: VirtAddr) -> TranslationResult {
         self.inner.translate(addr)
     }
+
+    fn translate_effective(&self, addr: VirtAddr) -> TranslationResult {
+        self.inner.translate_effective(addr)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,82 @@
+///! 扫描页表项的`ACCESSED`和`DIRTY`标志位，用于跟踪工作集
+use alloc::vec::Vec;
+
+use crate::arch::intel::x64::address::{align_down, VirtAddr, VirtualAddress};
+use crate::arch::intel::x64::paging::{Page, Page1GB, Page2MB, Page4KB, PageSize};
+use crate::arch::intel::x64::paging::flags::PageTableFlags;
+use crate::arch::intel::x64::paging::mapper::{MapAllSize, Mapper, MapperFlushBatch};
+use crate::arch::intel::x64::paging::mapper::audit::KERNEL_SPACE_START;
+use crate::arch::intel::x64::paging::result::{FlagUpdateError, TranslationResult};
+
+/// 单个已映射页面（或大页面）的访问状态
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct PageAccessState {
+    /// 页面的起始地址
+    pub start: VirtAddr,
+    /// 页面大小 4KB 2MB, 1GB
+    pub size: u64,
+    /// 自上次清除后CPU是否访问过该页面
+    pub accessed: bool,
+    /// 自上次清除后CPU是否写入过该页面
+    pub dirty: bool,
+}
+
+/// 扫描`[start, start + len)`范围内所有已映射页面的`ACCESSED`和`DIRTY`标志位
+/// 没有映射的页面会被跳过，部分位于范围内的大页面也会被完整报告一次，
+/// 范围跨越非Canonical地址空洞时会直接跳到高半部分继续扫描
+///
+/// `clear`中包含的`ACCESSED`和`DIRTY`标志位会在读取后被清除，其他标志位会被忽略
+/// 被修改的页面合并在返回的`MapperFlushBatch`中，调用者需要在扫描结束后统一刷新TLB
+///
+/// # Safety
+/// 清除`DIRTY`标志位后，调用者需要自行保证不会丢失页面的修改信息
+pub unsafe fn scan_access_bits<M: MapAllSize>(mapper: &mut M, start: VirtAddr, len: u64, clear: PageTableFlags)
+                                               -> Result<(Vec<PageAccessState>, MapperFlushBatch), FlagUpdateError> {
+    let clear = clear & (PageTableFlags::ACCESSED | PageTableFlags::DIRTY);
+    let end = start.as_u64().saturating_add(len);
+    let mut states = Vec::new();
+    let mut batch = MapperFlushBatch::new();
+
+    let mut current = align_down(start.as_u64(), Page4KB::P_SIZE);
+    while current < end {
+        let addr = match VirtAddr::try_new(current) {
+            Ok(addr) => addr,
+            Err(_) => {
+                current = KERNEL_SPACE_START;
+                continue;
+            }
+        };
+        let (flags, offset, size) = match mapper.translate(addr) {
+            TranslationResult::Frame4KB { offset, flags, .. } => (flags, offset, Page4KB::P_SIZE),
+            TranslationResult::Frame2MB { offset, flags, .. } => (flags, offset, Page2MB::P_SIZE),
+            TranslationResult::Frame1GB { offset, flags, .. } => (flags, offset, Page1GB::P_SIZE),
+            TranslationResult::PageNotMapped | TranslationResult::InvalidFrameAddress(_) => {
+                current = current.saturating_add(Page4KB::P_SIZE);
+                continue;
+            }
+        };
+        let page_start = VirtAddr::new(current - offset);
+        states.push(PageAccessState {
+            start: page_start,
+            size,
+            accessed: flags.contains(PageTableFlags::ACCESSED),
+            dirty: flags.contains(PageTableFlags::DIRTY),
+        });
+
+        if flags.intersects(clear) {
+            let new_flags = flags - clear;
+            if size == Page4KB::P_SIZE {
+                let page = Page::<Page4KB>::from_start_address(page_start).unwrap();
+                batch.push(<M as Mapper<Page4KB>>::update_flags(mapper, page, new_flags)?);
+            } else if size == Page2MB::P_SIZE {
+                let page = Page::<Page2MB>::from_start_address(page_start).unwrap();
+                batch.push(<M as Mapper<Page2MB>>::update_flags(mapper, page, new_flags)?);
+            } else {
+                let page = Page::<Page1GB>::from_start_address(page_start).unwrap();
+                batch.push(<M as Mapper<Page1GB>>::update_flags(mapper, page, new_flags)?);
+            }
+        }
+        current = page_start.as_u64().saturating_add(size);
+    }
+    Ok((states, batch))
+}
@@ -2,7 +2,7 @@ pub use allocator::{FrameAllocator, UnusedFrame};
 pub use frame::Frame;
 pub use page::{NotGiantPageSize, Page, Page1GB, Page2MB, Page4KB, PageRange, PageRangeInclude, PageSize};
 pub use page_ops::{PageIndex, PageOffset};
-pub use page_table::{ENTRY_COUNT, PageTable, PageTableEntry};
+pub use page_table::{ENTRY_COUNT, HierarchicalLevel, Level1, Level2, Level3, Level4, PageTable, PageTableEntry, TableLevel};
 
 use crate::arch::intel::chips::control::CR3;
 use crate::arch::intel::chips::msr_set::Efer;
@@ -19,6 +19,8 @@ pub mod mapper;
 pub mod result;
 pub mod frame_allocator;
 pub mod flags;
+pub mod uaccess;
+pub mod swap;
 
 pub struct PagingArgs {
     pub pml4t_base_addr: u64,
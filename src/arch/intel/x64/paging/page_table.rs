@@ -1,10 +1,12 @@
 use core::fmt;
+use core::marker::PhantomData;
 use core::ops::{Index, IndexMut};
 
-use crate::arch::intel::x64::address::{PhysAddr, PhysicalAddress};
+use crate::arch::intel::x64::address::{PhysAddr, PhysicalAddress, VirtAddr, VirtualAddress};
 use crate::arch::intel::x64::paging::{Frame, Page4KB, PageSize};
 use crate::arch::intel::x64::paging::flags::PageTableFlags;
 use crate::arch::intel::x64::paging::result::FrameError;
+use crate::arch::intel::x64::paging::swap::SwapEntry;
 
 use super::PageIndex;
 
@@ -64,6 +66,16 @@ impl PageTableEntry {
     pub fn set_flags(&mut self, flags: PageTableFlags) {
         self.entry = self.addr().as_u64() | flags.bits()
     }
+
+    /// 如果当前entry保存的是被换出页面的交换项，返回交换项以及换出前的页面属性
+    pub fn swap_entry(&self) -> Option<(SwapEntry, PageTableFlags)> {
+        SwapEntry::decode(self.entry)
+    }
+
+    /// 将entry设置为不存在的交换项，`flags`中只会保留`SwapEntry::SAVED_FLAGS`
+    pub fn set_swap_entry(&mut self, swap: SwapEntry, flags: PageTableFlags) {
+        self.entry = swap.encode(flags)
+    }
 }
 
 impl fmt::Debug for PageTableEntry {
@@ -77,17 +89,106 @@ impl fmt::Debug for PageTableEntry {
 
 pub const ENTRY_COUNT: usize = 512;
 
+/// 页表的层级，不同层级的页表项对标志位的解释不同
+pub trait TableLevel {
+    /// 层级编号，4级页表为4，1级页表为1
+    const LEVEL: u8;
+    /// 该层级的页表项是否可以映射大页面，只有3级页表（1GB）和2级页表（2MB）可以
+    const HUGE_PAGE_SUPPORTED: bool;
+    /// 该层级页表项中有意义的标志位
+    const VALID_FLAGS: PageTableFlags;
+
+    /// 页表项是否映射了大页面
+    fn is_huge(entry: &PageTableEntry) -> bool {
+        Self::HUGE_PAGE_SUPPORTED && entry.flags().contains(PageTableFlags::HUGE_PAGE)
+    }
+
+    /// 按照该层级的规则解释页表项的标志位
+    /// `GLOBAL`和`DIRTY`只在映射页面的页表项中有效，指向下一级页表时会被清除
+    fn entry_flags(entry: &PageTableEntry) -> PageTableFlags {
+        let flags = entry.flags() & Self::VALID_FLAGS;
+        if Self::LEVEL == 1 || Self::is_huge(entry) {
+            flags
+        } else {
+            flags - PageTableFlags::GLOBAL - PageTableFlags::DIRTY
+        }
+    }
+}
+
+/// 含有下一级页表的层级
+pub trait HierarchicalLevel: TableLevel {
+    /// 下一级页表的层级
+    type NextLevel: TableLevel;
+}
+
+/// 4级页表（PML4）
+#[derive(Debug)]
+pub enum Level4 {}
+
+/// 3级页表（PDPT）
+#[derive(Debug)]
+pub enum Level3 {}
+
+/// 2级页表（PD）
+#[derive(Debug)]
+pub enum Level2 {}
+
+/// 1级页表（PT）
+#[derive(Debug)]
+pub enum Level1 {}
+
+impl TableLevel for Level4 {
+    const LEVEL: u8 = 4;
+    const HUGE_PAGE_SUPPORTED: bool = false;
+    /// 4级页表项的第7位保留（必须为0）
+    const VALID_FLAGS: PageTableFlags = PageTableFlags::from_bits_truncate(!PageTableFlags::HUGE_PAGE.bits());
+}
+
+impl TableLevel for Level3 {
+    const LEVEL: u8 = 3;
+    const HUGE_PAGE_SUPPORTED: bool = true;
+    const VALID_FLAGS: PageTableFlags = PageTableFlags::all();
+}
+
+impl TableLevel for Level2 {
+    const LEVEL: u8 = 2;
+    const HUGE_PAGE_SUPPORTED: bool = true;
+    const VALID_FLAGS: PageTableFlags = PageTableFlags::all();
+}
+
+impl TableLevel for Level1 {
+    const LEVEL: u8 = 1;
+    const HUGE_PAGE_SUPPORTED: bool = false;
+    /// 1级页表项的第7位是PAT，不表示大页面
+    const VALID_FLAGS: PageTableFlags = PageTableFlags::from_bits_truncate(!PageTableFlags::HUGE_PAGE.bits());
+}
+
+impl HierarchicalLevel for Level4 {
+    type NextLevel = Level3;
+}
+
+impl HierarchicalLevel for Level3 {
+    type NextLevel = Level2;
+}
+
+impl HierarchicalLevel for Level2 {
+    type NextLevel = Level1;
+}
+
+/// 页表，`L`表示页表所在的层级，默认为4级页表
 #[repr(align(4096))]
 #[repr(C)]
-pub struct PageTable {
+pub struct PageTable<L: TableLevel = Level4> {
     entries: [PageTableEntry; ENTRY_COUNT],
+    level: PhantomData<L>,
 }
 
-impl PageTable {
+impl<L: TableLevel> PageTable<L> {
     /// 创建一个空的页表
     pub const fn new() -> Self {
         PageTable {
-            entries: [PageTableEntry::new(); ENTRY_COUNT]
+            entries: [PageTableEntry::new(); ENTRY_COUNT],
+            level: PhantomData,
         }
     }
     /// 清空表中所有内容
@@ -104,10 +205,43 @@ impl PageTable {
     pub fn iter_mut(&mut self) -> impl Iterator<Item=&mut PageTableEntry> {
         self.entries.iter_mut()
     }
+    /// 按照页表所在层级解释给定页表项的标志位
+    pub fn entry_flags(&self, index: PageIndex) -> PageTableFlags {
+        L::entry_flags(&self[index])
+    }
+}
+
+impl<L: HierarchicalLevel> PageTable<L> {
+    /// 在递归映射的页表中计算下一级页表的地址
+    /// 页表项不存在或映射了大页面时返回None
+    fn next_table_address(&self, index: PageIndex) -> Option<VirtAddr> {
+        let entry = &self[index];
+        if !entry.flags().contains(PageTableFlags::PRESENT) || L::is_huge(entry) {
+            return None;
+        }
+        let table_addr = self as *const _ as u64;
+        Some(VirtAddr::new_unchecked((table_addr << 9) | (u64::from(u16::from(index)) << 12)))
+    }
+
+    /// 获取第`index`项指向的下一级页表
+    ///
+    /// # Safety
+    /// 页表必须通过递归地址访问，即地址形如`0o_rrr_xxx_xxx_xxx_0000`
+    pub unsafe fn next_table(&self, index: PageIndex) -> Option<&PageTable<L::NextLevel>> {
+        self.next_table_address(index).map(|addr| &*addr.as_ptr::<PageTable<L::NextLevel>>())
+    }
+
+    /// 获取第`index`项指向的下一级页表的可变引用
+    ///
+    /// # Safety
+    /// 同`PageTable::next_table`
+    pub unsafe fn next_table_mut(&mut self, index: PageIndex) -> Option<&mut PageTable<L::NextLevel>> {
+        self.next_table_address(index).map(|addr| &mut *addr.as_mut_ptr::<PageTable<L::NextLevel>>())
+    }
 }
 
 // ----------------- 为 Page实现索引功能 支持usize索引和PageIndex索引 -----------------
-impl Index<usize> for PageTable {
+impl<L: TableLevel> Index<usize> for PageTable<L> {
     type Output = PageTableEntry;
 
     fn index(&self, index: usize) -> &Self::Output {
@@ -115,13 +249,13 @@ impl Index<usize> for PageTable {
     }
 }
 
-impl IndexMut<usize> for PageTable {
+impl<L: TableLevel> IndexMut<usize> for PageTable<L> {
     fn index_mut(&mut self, index: usize) -> &mut Self::Output {
         &mut self.entries[index]
     }
 }
 
-impl Index<PageIndex> for PageTable {
+impl<L: TableLevel> Index<PageIndex> for PageTable<L> {
     type Output = PageTableEntry;
 
     fn index(&self, index: PageIndex) -> &Self::Output {
@@ -129,14 +263,14 @@ impl Index<PageIndex> for PageTable {
     }
 }
 
-impl IndexMut<PageIndex> for PageTable {
+impl<L: TableLevel> IndexMut<PageIndex> for PageTable<L> {
     fn index_mut(&mut self, index: PageIndex) -> &mut Self::Output {
         &mut self.entries[cast::usize(u16::from(index))]
     }
 }
 
-impl fmt::Debug for PageTable {
+impl<L: TableLevel> fmt::Debug for PageTable<L> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         self.entries[..].fmt(f)
     }
-}
\ No newline at end of file
+}
@@ -1,5 +1,6 @@
-use crate::arch::intel::x64::address::PhysAddr;
+use crate::arch::intel::x64::address::{PhysAddr, VirtAddr};
 use crate::arch::intel::x64::paging::{Frame, Page1GB, Page2MB, Page4KB, PageSize, UnusedFrame};
+use crate::arch::intel::x64::paging::flags::PageTableFlags;
 
 pub type Result<T> = core::result::Result<T, Error>;
 
@@ -8,14 +9,20 @@ pub enum TranslationResult {
     Frame4KB {
         frame: Frame<Page4KB>,
         offset: u64,
+        /// 映射该页面的页表项的flags，1级页表项中的`HUGE_PAGE`位表示PAT
+        flags: PageTableFlags,
     },
     Frame2MB {
         frame: Frame<Page2MB>,
         offset: u64,
+        /// 映射该页面的页表项的flags
+        flags: PageTableFlags,
     },
     Frame1GB {
         frame: Frame<Page1GB>,
         offset: u64,
+        /// 映射该页面的页表项的flags
+        flags: PageTableFlags,
     },
     PageNotMapped,
     InvalidFrameAddress(PhysAddr),
@@ -64,6 +71,8 @@ pub enum MapToError<S: PageSize> {
 pub enum FlagUpdateError {
     PageNotMapped,
     ParentEntryHugePage,
+    /// 拆分覆盖该页面的大页面失败
+    SplitFailed(SplitError),
 }
 
 #[derive(Debug)]
@@ -78,6 +87,100 @@ pub enum UnmapError {
     PageNotMapped,
     ParentEntryHugePage,
     InvalidFrameAddress(PhysAddr),
+    /// 拆分覆盖该页面的大页面失败
+    SplitFailed(SplitError),
+}
+
+#[derive(Debug)]
+pub enum ProtectError {
+    /// 起始地址没有按4KB对齐
+    NotAligned(VirtAddr),
+    /// 范围内的页面没有映射
+    PageNotMapped(VirtAddr),
+    /// 范围超出了地址空间的末尾
+    AddressOverflow,
+    /// 范围跨越了非Canonical地址空洞，包含第一个非Canonical地址
+    NonCanonicalAddress(u64),
+    /// 拆分范围边缘的大页面失败
+    SplitFailed(SplitError),
+    /// 更新页面属性失败
+    FlagUpdateFailed(FlagUpdateError),
+}
+
+#[derive(Debug)]
+pub enum SplitError {
+    /// 页面没有映射
+    PageNotMapped,
+    /// 页表项映射的不是大页面
+    NotHugePage,
+    /// 物理帧分配错误
+    FrameAllocateFailed,
+    /// 4级页表中没有可用于临时访问新页表的空闲项
+    NoFreeEntry,
+}
+
+#[derive(Debug)]
+pub enum MergeError {
+    /// 页面没有映射
+    PageNotMapped,
+    /// 页表项已经是大页面
+    AlreadyHugePage,
+    /// 下级页表中的页面没有全部映射、物理地址不连续或页面属性不一致
+    NotMergeable,
+}
+
+#[derive(Debug)]
+pub enum DirectMapError {
+    /// 直接映射的起始地址没有按4KB对齐
+    NotAligned(VirtAddr),
+    /// 物理地址加上偏移量后超出了虚拟地址空间
+    AddressOverflow(PhysAddr),
+    /// 虚拟地址已映射到其他物理地址
+    Conflict(VirtAddr),
+    /// 物理帧分配错误
+    FrameAllocateFailed,
+}
+
+#[derive(Debug)]
+pub enum InactiveTableError {
+    /// 临时项不能是当前页表的递归项
+    RecursiveIndex,
+    /// 当前页表中用作临时项的页表项已被使用
+    ScratchEntryInUse,
+    /// 4级页表中没有可用作临时项的空闲项
+    NoFreeEntry,
+    /// 给定的物理帧是当前正在使用的页表
+    TableIsActive,
+}
+
+#[derive(Debug)]
+pub enum UserAccessError {
+    /// 地址范围溢出
+    AddressOverflow,
+    /// 地址范围不在用户空间（低半部分Canonical地址）内
+    NotUserAddress(VirtAddr),
+    /// 页面没有映射
+    PageNotMapped(VirtAddr),
+    /// 页面没有设置`USER_ACCESSIBLE`
+    NotUserAccessible(VirtAddr),
+    /// 页面没有设置`WRITABLE`
+    NotWritable(VirtAddr),
+}
+
+#[derive(Debug)]
+pub enum SwapError {
+    /// 页面没有映射
+    PageNotMapped,
+    /// 上级页表项是大页面
+    ParentEntryHugePage,
+    /// 页表项不是交换项
+    NotSwapEntry,
+    /// 交换设备没有空闲的槽位
+    DeviceFull,
+    /// 槽位超出交换设备的范围或没有被使用
+    InvalidSlot(u64),
+    /// 物理帧分配错误
+    FrameAllocateFailed,
 }
 
 #[derive(Debug, Eq, PartialEq)]
@@ -152,3 +255,35 @@ impl From<PageTableWalkError> for TranslateError {
     }
 }
 
+impl From<PageTableWalkError> for SwapError {
+    fn from(err: PageTableWalkError) -> Self {
+        match err {
+            PageTableWalkError::MappedToHugePage => SwapError::ParentEntryHugePage,
+            PageTableWalkError::NotMapped => SwapError::PageNotMapped,
+        }
+    }
+}
+
+impl From<SplitError> for FlagUpdateError {
+    fn from(err: SplitError) -> Self {
+        FlagUpdateError::SplitFailed(err)
+    }
+}
+
+impl From<SplitError> for UnmapError {
+    fn from(err: SplitError) -> Self {
+        UnmapError::SplitFailed(err)
+    }
+}
+
+impl From<SplitError> for ProtectError {
+    fn from(err: SplitError) -> Self {
+        ProtectError::SplitFailed(err)
+    }
+}
+
+impl From<FlagUpdateError> for ProtectError {
+    fn from(err: FlagUpdateError) -> Self {
+        ProtectError::FlagUpdateFailed(err)
+    }
+}
@@ -0,0 +1,284 @@
+///! 页面换出与换入
+///! 页面被换出后，不存在的页表项中保存交换设备编号和槽位，换入时根据页表项恢复页面
+use alloc::vec::Vec;
+
+use bit_field::BitField;
+
+use crate::arch::intel::instructions::page_table::flush;
+use crate::arch::intel::x64::address::VirtualAddress;
+use crate::arch::intel::x64::paging::{Frame, FrameAllocator, Page, Page4KB, PageSize, UnusedFrame};
+use crate::arch::intel::x64::paging::flags::PageTableFlags;
+use crate::arch::intel::x64::paging::mapper::{EntryAccess, MapperFlush};
+use crate::arch::intel::x64::paging::result::SwapError;
+
+/// 交换设备中一个槽位的大小
+pub const SWAP_PAGE_SIZE: usize = Page4KB::P_SIZE as usize;
+
+/// 交换项，保存在不存在（`PRESENT`为0）的页表项中
+/// 不存在的页表项除第0位外都会被CPU忽略，编码方式如下
+///
+/// |63|62 - 59 |58  -  52| 51   -   12 |11-10| 9 | 8 | 7 |6-5| 4 | 3 |2|1|0|
+/// +--+--------+---------+-------------+-----+---+---+---+---+---+---+-+-+-+
+/// |NX|reserved| device  |    slot     |  0  | 1 | G |PAT| 0 |PCD|PWT|U|W|0|
+/// +--+--------+---------+-------------+-----+---+---+---+---+---+---+-+-+-+
+///
+/// `BIT_9`用于区分交换项和空页表项，其余标志位保存换出前的页面属性，
+/// 其中第7位与1级页表项相同表示PAT，换入后页面的缓存类型和全局属性保持不变
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SwapEntry {
+    device: u8,
+    slot: u64,
+}
+
+impl SwapEntry {
+    /// 最大的交换设备编号
+    pub const MAX_DEVICE: u8 = 0x7F;
+    /// 最大的槽位
+    pub const MAX_SLOT: u64 = (1 << 40) - 1;
+    /// 交换项中保存的页面属性
+    pub const SAVED_FLAGS: PageTableFlags = PageTableFlags::from_bits_truncate(
+        PageTableFlags::WRITABLE.bits() | PageTableFlags::USER_ACCESSIBLE.bits() | PageTableFlags::NO_EXECUTE.bits()
+            | PageTableFlags::WRITE_THROUGH.bits() | PageTableFlags::NO_CACHE.bits() | PageTableFlags::HUGE_PAGE.bits()
+            | PageTableFlags::GLOBAL.bits()
+    );
+
+    /// 根据交换设备编号和槽位创建交换项，超出范围时将会Panic
+    pub fn new(device: u8, slot: u64) -> Self {
+        assert!(device <= Self::MAX_DEVICE, "swap device number must be less than 128");
+        assert!(slot <= Self::MAX_SLOT, "swap slot must be less than 2^40");
+        Self { device, slot }
+    }
+
+    /// 交换设备编号
+    pub fn device(&self) -> u8 {
+        self.device
+    }
+
+    /// 交换设备中的槽位
+    pub fn slot(&self) -> u64 {
+        self.slot
+    }
+
+    /// 将交换项编码为不存在的页表项
+    pub fn encode(&self, flags: PageTableFlags) -> u64 {
+        let mut entry = (flags & Self::SAVED_FLAGS).bits() | PageTableFlags::BIT_9.bits();
+        entry.set_bits(12..52, self.slot);
+        entry.set_bits(52..59, u64::from(self.device));
+        entry
+    }
+
+    /// 从页表项中解码交换项和换出前的页面属性，如果页表项不是交换项则返回None
+    pub fn decode(entry: u64) -> Option<(SwapEntry, PageTableFlags)> {
+        let flags = PageTableFlags::from_bits_truncate(entry);
+        if flags.contains(PageTableFlags::PRESENT) || !flags.contains(PageTableFlags::BIT_9) {
+            return None;
+        }
+        let swap = SwapEntry {
+            device: entry.get_bits(52..59) as u8,
+            slot: entry.get_bits(12..52),
+        };
+        Some((swap, flags & Self::SAVED_FLAGS))
+    }
+}
+
+/// 交换设备，以页面为单位按槽位读写
+pub trait SwapDevice {
+    /// 设备的槽位总数
+    fn slot_count(&self) -> u64;
+    /// 分配一个空闲槽位，没有空闲槽位时返回None
+    fn alloc_slot(&mut self) -> Option<u64>;
+    /// 释放一个槽位
+    fn free_slot(&mut self, slot: u64);
+    /// 从给定槽位读取一个页面
+    fn read_page(&mut self, slot: u64, buf: &mut [u8; SWAP_PAGE_SIZE]) -> Result<(), SwapError>;
+    /// 将一个页面写入给定槽位
+    fn write_page(&mut self, slot: u64, buf: &[u8; SWAP_PAGE_SIZE]) -> Result<(), SwapError>;
+}
+
+/// 使用内存作为后端的交换设备
+/// `swap_out`和`swap_in`需要当前正在使用的页表，只能在内核中运行，该设备本身可以在主机上测试
+pub struct RamSwapDevice {
+    data: Vec<u8>,
+    used: Vec<bool>,
+}
+
+impl RamSwapDevice {
+    /// 创建含有`slots`个槽位的交换设备
+    pub fn new(slots: usize) -> Self {
+        Self {
+            data: vec![0; slots * SWAP_PAGE_SIZE],
+            used: vec![false; slots],
+        }
+    }
+
+    /// 已使用的槽位个数
+    pub fn used_slots(&self) -> usize {
+        self.used.iter().filter(|used| **used).count()
+    }
+
+    fn check_slot(&self, slot: u64) -> Result<usize, SwapError> {
+        let index = slot as usize;
+        if index >= self.used.len() || !self.used[index] {
+            return Err(SwapError::InvalidSlot(slot));
+        }
+        Ok(index * SWAP_PAGE_SIZE)
+    }
+}
+
+impl SwapDevice for RamSwapDevice {
+    fn slot_count(&self) -> u64 {
+        self.used.len() as u64
+    }
+
+    fn alloc_slot(&mut self) -> Option<u64> {
+        let index = self.used.iter().position(|used| !*used)?;
+        self.used[index] = true;
+        Some(index as u64)
+    }
+
+    fn free_slot(&mut self, slot: u64) {
+        if let Some(used) = self.used.get_mut(slot as usize) {
+            *used = false;
+        }
+    }
+
+    fn read_page(&mut self, slot: u64, buf: &mut [u8; SWAP_PAGE_SIZE]) -> Result<(), SwapError> {
+        let offset = self.check_slot(slot)?;
+        buf.copy_from_slice(&self.data[offset..offset + SWAP_PAGE_SIZE]);
+        Ok(())
+    }
+
+    fn write_page(&mut self, slot: u64, buf: &[u8; SWAP_PAGE_SIZE]) -> Result<(), SwapError> {
+        let offset = self.check_slot(slot)?;
+        self.data[offset..offset + SWAP_PAGE_SIZE].copy_from_slice(buf);
+        Ok(())
+    }
+}
+
+/// 将已映射的页面换出到交换设备中
+/// 页面内容写入新分配的槽位，页表项被替换为交换项，刷新TLB后物理帧交还给`allocator`
+///
+/// # Safety
+/// `mapper`必须是当前正在使用的页表，页面内容通过页面自身的虚拟地址读取，
+/// 调用者需要保证换出期间没有其他CPU访问该页面
+pub unsafe fn swap_out<M, D, A>(mapper: &mut M, device: &mut D, device_number: u8, page: Page<Page4KB>, allocator: &mut A)
+                                -> Result<SwapEntry, SwapError>
+    where M: EntryAccess, D: SwapDevice, A: FrameAllocator<Page4KB> {
+    let entry = mapper.entry_mut(page)?;
+    let flags = entry.flags();
+    if !flags.contains(PageTableFlags::PRESENT) {
+        return Err(SwapError::PageNotMapped);
+    }
+    // 1级页表项的第7位表示PAT而不是大页面，因此直接读取物理地址而不使用`frame`
+    let frame = Frame::<Page4KB>::include_address(entry.addr());
+
+    let slot = device.alloc_slot().ok_or(SwapError::DeviceFull)?;
+    let content = &*(page.start_address().as_ptr::<[u8; SWAP_PAGE_SIZE]>());
+    if let Err(err) = device.write_page(slot, content) {
+        device.free_slot(slot);
+        return Err(err);
+    }
+
+    let swap = SwapEntry::new(device_number, slot);
+    entry.set_swap_entry(swap, flags);
+    flush(page.start_address());
+    allocator.dealloc(UnusedFrame::new(frame));
+    Ok(swap)
+}
+
+/// 将被换出的页面换入内存
+/// 从`allocator`分配新的物理帧，读取交换设备中的页面内容，恢复换出前的页面属性并释放槽位
+///
+/// `device`必须是交换项中设备编号所对应的交换设备
+///
+/// # Safety
+/// `mapper`必须是当前正在使用的页表，页面内容通过页面自身的虚拟地址写入
+pub unsafe fn swap_in<M, D, A>(mapper: &mut M, device: &mut D, page: Page<Page4KB>, allocator: &mut A)
+                               -> Result<MapperFlush<Page4KB>, SwapError>
+    where M: EntryAccess, D: SwapDevice, A: FrameAllocator<Page4KB> {
+    let entry = mapper.entry_mut(page)?;
+    let (swap, flags) = entry.swap_entry().ok_or(SwapError::NotSwapEntry)?;
+    let frame = allocator.alloc().ok_or(SwapError::FrameAllocateFailed)?;
+
+    // 先以可写的方式映射，写入页面内容后再恢复原来的页面属性
+    // 不存在的页表项不会被缓存在TLB中，因此这里不需要刷新
+    // `flags`中的`HUGE_PAGE`是PAT，因此使用`set_addr`而不是会拒绝该位的`set_frame`
+    entry.set_addr(frame.start_address(), flags | PageTableFlags::PRESENT | PageTableFlags::WRITABLE);
+    let content = &mut *(page.start_address().as_mut_ptr::<[u8; SWAP_PAGE_SIZE]>());
+    if let Err(err) = device.read_page(swap.slot(), content) {
+        entry.set_swap_entry(swap, flags);
+        flush(page.start_address());
+        allocator.dealloc(frame);
+        return Err(err);
+    }
+    entry.set_flags(flags | PageTableFlags::PRESENT);
+    device.free_slot(swap.slot());
+    Ok(MapperFlush::new(page))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_entry_round_trip() {
+        let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::USER_ACCESSIBLE
+            | PageTableFlags::NO_EXECUTE | PageTableFlags::WRITE_THROUGH | PageTableFlags::NO_CACHE
+            | PageTableFlags::HUGE_PAGE | PageTableFlags::GLOBAL | PageTableFlags::ACCESSED | PageTableFlags::DIRTY;
+        for &(device, slot) in &[(0, 0), (1, 0x1234), (SwapEntry::MAX_DEVICE, SwapEntry::MAX_SLOT)] {
+            let swap = SwapEntry::new(device, slot);
+            let entry = swap.encode(flags);
+            assert!(!PageTableFlags::from_bits_truncate(entry).contains(PageTableFlags::PRESENT));
+            assert_eq!(SwapEntry::decode(entry), Some((swap, flags & SwapEntry::SAVED_FLAGS)));
+        }
+    }
+
+    #[test]
+    fn ram_device_read_write() {
+        let mut device = RamSwapDevice::new(2);
+        assert_eq!(device.slot_count(), 2);
+        let slot = device.alloc_slot().unwrap();
+        let mut page = [0u8; SWAP_PAGE_SIZE];
+        page[0] = 0x5A;
+        page[SWAP_PAGE_SIZE - 1] = 0xA5;
+        device.write_page(slot, &page).unwrap();
+        let mut buf = [0u8; SWAP_PAGE_SIZE];
+        device.read_page(slot, &mut buf).unwrap();
+        assert_eq!(&buf[..], &page[..]);
+        assert_eq!(device.used_slots(), 1);
+
+        device.free_slot(slot);
+        assert_eq!(device.used_slots(), 0);
+        assert_eq!(device.alloc_slot(), Some(slot));
+    }
+
+    #[test]
+    fn ram_device_full() {
+        // 没有空闲槽位时`alloc_slot`返回None，`swap_out`将其报告为`SwapError::DeviceFull`
+        let mut device = RamSwapDevice::new(2);
+        assert_eq!(device.alloc_slot(), Some(0));
+        assert_eq!(device.alloc_slot(), Some(1));
+        assert_eq!(device.alloc_slot(), None);
+        device.free_slot(0);
+        assert_eq!(device.alloc_slot(), Some(0));
+    }
+
+    #[test]
+    fn ram_device_rejects_invalid_slots() {
+        let mut device = RamSwapDevice::new(1);
+        let mut buf = [0u8; SWAP_PAGE_SIZE];
+        // 没有分配的槽位和超出范围的槽位都不能读写
+        assert!(matches!(device.read_page(0, &mut buf), Err(SwapError::InvalidSlot(0))));
+        assert!(matches!(device.write_page(1, &buf), Err(SwapError::InvalidSlot(1))));
+        let slot = device.alloc_slot().unwrap();
+        device.free_slot(slot);
+        assert!(matches!(device.read_page(slot, &mut buf), Err(SwapError::InvalidSlot(_))));
+    }
+
+    #[test]
+    fn decode_rejects_other_entries() {
+        assert_eq!(SwapEntry::decode(0), None);
+        let present = SwapEntry::new(1, 1).encode(PageTableFlags::empty()) | PageTableFlags::PRESENT.bits();
+        assert_eq!(SwapEntry::decode(present), None);
+    }
+}
@@ -0,0 +1,136 @@
+///! 安全的用户空间内存访问
+///! 在访问用户内存前通过页表检查整个地址范围，以错误值代替缺页异常
+use core::cmp::min;
+use core::ptr;
+
+use crate::arch::intel::chips::control::CR4;
+use crate::arch::intel::chips::flags::CR4Flags;
+use crate::arch::intel::instructions::rflags::{clac, stac};
+use crate::arch::intel::x64::address::{align_down, VirtAddr, VirtualAddress};
+use crate::arch::intel::x64::paging::{Page1GB, Page2MB, Page4KB, PageSize};
+use crate::arch::intel::x64::paging::flags::PageTableFlags;
+use crate::arch::intel::x64::paging::mapper::MapAllSize;
+use crate::arch::intel::x64::paging::result::{TranslationResult, UserAccessError};
+
+/// 用户空间的结束地址（不包含），即低半部分Canonical地址的上界
+pub const USER_SPACE_END: u64 = 0x0000_8000_0000_0000;
+
+/// 检查`[addr, addr + len)`是否完全位于用户空间，并且每一页都已映射且设置了`USER_ACCESSIBLE`
+/// 当`write`为true时还要求每一页都设置了`WRITABLE`，两者都需要在映射该页面的每一级页表项中设置
+pub fn check_user_range<M: MapAllSize>(mapper: &M, addr: VirtAddr, len: usize, write: bool) -> Result<(), UserAccessError> {
+    if len == 0 {
+        return Ok(());
+    }
+    let start = addr.as_u64();
+    let end = start.checked_add(len as u64).ok_or(UserAccessError::AddressOverflow)?;
+    if end > USER_SPACE_END {
+        return Err(UserAccessError::NotUserAddress(addr));
+    }
+
+    let mut current = align_down(start, Page4KB::P_SIZE);
+    while current < end {
+        let page_addr = VirtAddr::new(current);
+        // 大页面只需要检查一次，直接跳到大页面的末尾
+        let (flags, remain) = match mapper.translate_effective(page_addr) {
+            TranslationResult::Frame4KB { offset, flags, .. } => (flags, Page4KB::P_SIZE - offset),
+            TranslationResult::Frame2MB { offset, flags, .. } => (flags, Page2MB::P_SIZE - offset),
+            TranslationResult::Frame1GB { offset, flags, .. } => (flags, Page1GB::P_SIZE - offset),
+            TranslationResult::PageNotMapped | TranslationResult::InvalidFrameAddress(_) => {
+                return Err(UserAccessError::PageNotMapped(page_addr));
+            }
+        };
+        if !flags.contains(PageTableFlags::PRESENT) {
+            return Err(UserAccessError::PageNotMapped(page_addr));
+        }
+        if !flags.contains(PageTableFlags::USER_ACCESSIBLE) {
+            return Err(UserAccessError::NotUserAccessible(page_addr));
+        }
+        if write && !flags.contains(PageTableFlags::WRITABLE) {
+            return Err(UserAccessError::NotWritable(page_addr));
+        }
+        current += remain;
+    }
+    Ok(())
+}
+
+/// 从用户空间地址`src`复制`dst.len()`字节到内核缓冲区`dst`
+///
+/// # Error
+/// * 地址范围不在用户空间内，或者其中有页面没有映射或用户不可访问时返回对应的错误，`dst`不会被修改
+///
+/// # Safety
+/// `mapper`必须是当前正在使用的页表，否则检查的是其他地址空间，复制时访问的地址可能没有映射。
+/// 检查与复制之间其他CPU取消映射该范围时复制依旧会引发缺页异常，调用者需要保证期间地址空间不会被修改
+pub unsafe fn copy_from_user<M: MapAllSize>(mapper: &M, dst: &mut [u8], src: VirtAddr) -> Result<(), UserAccessError> {
+    check_user_range(mapper, src, dst.len(), false)?;
+    with_user_access(|| {
+        ptr::copy_nonoverlapping(src.as_ptr::<u8>(), dst.as_mut_ptr(), dst.len());
+    });
+    Ok(())
+}
+
+/// 将内核缓冲区`src`复制到用户空间地址`dst`
+///
+/// # Error
+/// * 地址范围不在用户空间内，或者其中有页面没有映射、用户不可访问或不可写时返回对应的错误，用户内存不会被修改
+///
+/// # Safety
+/// `mapper`必须是当前正在使用的页表，否则检查的是其他地址空间，复制时访问的地址可能没有映射。
+/// 检查与复制之间其他CPU取消映射该范围时复制依旧会引发缺页异常，调用者需要保证期间地址空间不会被修改
+pub unsafe fn copy_to_user<M: MapAllSize>(mapper: &M, dst: VirtAddr, src: &[u8]) -> Result<(), UserAccessError> {
+    check_user_range(mapper, dst, src.len(), true)?;
+    with_user_access(|| {
+        ptr::copy_nonoverlapping(src.as_ptr(), dst.as_mut_ptr::<u8>(), src.len());
+    });
+    Ok(())
+}
+
+/// 从用户空间地址`src`复制以`\0`结尾的字符串到`dst`，最多复制`dst.len()`字节
+/// 返回字符串的长度（不包含`\0`），如果返回值等于`dst.len()`表示字符串已被截断
+///
+/// 逐页检查地址范围，因此字符串结尾之后没有映射的页面不会导致错误
+///
+/// # Safety
+/// `mapper`必须是当前正在使用的页表，否则检查的是其他地址空间，复制时访问的地址可能没有映射。
+/// 检查与复制之间其他CPU取消映射该范围时复制依旧会引发缺页异常，调用者需要保证期间地址空间不会被修改
+pub unsafe fn strncpy_from_user<M: MapAllSize>(mapper: &M, dst: &mut [u8], src: VirtAddr) -> Result<usize, UserAccessError> {
+    let mut copied = 0;
+    while copied < dst.len() {
+        let current = src.as_u64().checked_add(copied as u64).ok_or(UserAccessError::AddressOverflow)?;
+        let page_remain = (Page4KB::P_SIZE - current % Page4KB::P_SIZE) as usize;
+        let chunk = min(page_remain, dst.len() - copied);
+        let current = VirtAddr::try_new(current).map_err(|_| UserAccessError::NotUserAddress(src))?;
+        check_user_range(mapper, current, chunk, false)?;
+
+        let buf = &mut dst[copied..copied + chunk];
+        let found = with_user_access(|| {
+            let ptr = current.as_ptr::<u8>();
+            for (i, byte) in buf.iter_mut().enumerate() {
+                *byte = ptr::read_volatile(ptr.add(i));
+                if *byte == 0 {
+                    return Some(i);
+                }
+            }
+            None
+        });
+        if let Some(len) = found {
+            return Ok(copied + len);
+        }
+        copied += chunk;
+    }
+    Ok(dst.len())
+}
+
+/// 在允许内核访问用户页面的情况下执行`f`
+/// 如果CR4启用了SMAP，执行前使用`stac`临时关闭保护，执行后使用`clac`恢复
+fn with_user_access<F, R>(f: F) -> R where F: FnOnce() -> R {
+    let smap = CR4::flags().contains(CR4Flags::SUPERVISOR_MODE_ACCESS_PREVENTION);
+    if smap {
+        unsafe { stac() };
+    }
+    let ret = f();
+    if smap {
+        unsafe { clac() };
+    }
+    ret
+}
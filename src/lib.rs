@@ -1,5 +1,6 @@
 #![no_std]
 #![feature(llvm_asm)]
+#![cfg_attr(feature = "entry", feature(global_asm))]
 #![feature(allocator_api)]
 #![feature(const_fn)]
 #![feature(ptr_internals)]